@@ -0,0 +1,229 @@
+use crate::noise::Perlin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Tundra,
+    Ocean,
+}
+
+//small blocks scattered on top of the surface
+#[derive(Clone, Copy, Debug)]
+pub struct Decoration {
+    pub block: &'static str,
+    //chance per surface block
+    pub chance: f64,
+    pub height: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BiomeParams {
+    //surface height where the terrain noise is 0
+    pub base_height: f64,
+    //how far the noise moves the surface up or down
+    pub amplitude: f64,
+    pub surface: &'static str,
+    //the few blocks right below the surface
+    pub filler: &'static str,
+    pub decoration: Option<Decoration>,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 5] = [
+        Biome::Plains,
+        Biome::Desert,
+        Biome::Mountains,
+        Biome::Tundra,
+        Biome::Ocean,
+    ];
+
+    pub fn params(self) -> BiomeParams {
+        match self {
+            Biome::Plains => BiomeParams {
+                base_height: 8.0,
+                amplitude: 3.0,
+                surface: "grass",
+                filler: "dirt",
+                decoration: Some(Decoration {
+                    block: "flower",
+                    chance: 0.02,
+                    height: 1,
+                }),
+            },
+            Biome::Desert => BiomeParams {
+                base_height: 8.0,
+                amplitude: 2.0,
+                surface: "sand",
+                filler: "sand",
+                decoration: Some(Decoration {
+                    block: "cactus",
+                    chance: 0.005,
+                    height: 3,
+                }),
+            },
+            Biome::Mountains => BiomeParams {
+                base_height: 10.0,
+                amplitude: 6.0,
+                surface: "stone",
+                filler: "stone",
+                decoration: None,
+            },
+            Biome::Tundra => BiomeParams {
+                base_height: 8.0,
+                amplitude: 3.0,
+                surface: "snow",
+                filler: "dirt",
+                decoration: None,
+            },
+            Biome::Ocean => BiomeParams {
+                base_height: 3.0,
+                amplitude: 2.0,
+                surface: "sand",
+                filler: "sand",
+                decoration: None,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Plains => "plains",
+            Biome::Desert => "desert",
+            Biome::Mountains => "mountains",
+            Biome::Tundra => "tundra",
+            Biome::Ocean => "ocean",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Biome::ALL.into_iter().find(|biome| biome.name() == name)
+    }
+
+    //temperature and humidity are both roughly in -1..1
+    pub fn from_climate(temperature: f64, humidity: f64) -> Self {
+        if humidity > 0.35 {
+            Biome::Ocean
+        } else if temperature < -0.25 {
+            Biome::Tundra
+        } else if temperature > 0.25 && humidity < 0.0 {
+            Biome::Desert
+        } else if humidity < -0.25 {
+            Biome::Mountains
+        } else {
+            Biome::Plains
+        }
+    }
+}
+
+pub struct BiomeMap {
+    temperature: Perlin,
+    humidity: Perlin,
+    //in blocks, roughly the size of a single biome
+    pub scale: f64,
+    //distance between the samples that get averaged for the height
+    //parameters, bigger means softer transitions between biomes
+    pub blend_distance: i32,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            //different seeds so the two maps aren't the same noise
+            temperature: Perlin::new(seed ^ 0x7E3F_1A2B),
+            humidity: Perlin::new(seed ^ 0x4D5C_9E01),
+            scale: 256.0,
+            blend_distance: 8,
+        }
+    }
+
+    pub fn climate_at(&self, x: i32, z: i32) -> (f64, f64) {
+        let (x, z) = (x as f64 / self.scale, z as f64 / self.scale);
+        (
+            self.temperature.fbm_2d(x, z, 2) * 2.0,
+            self.humidity.fbm_2d(x, z, 2) * 2.0,
+        )
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let (temperature, humidity) = self.climate_at(x, z);
+        Biome::from_climate(temperature, humidity)
+    }
+
+    //base height and amplitude averaged over a 3x3 grid of samples
+    //around the column so there are no cliffs at biome borders
+    pub fn blended_height_params(&self, x: i32, z: i32) -> (f64, f64) {
+        let mut base_height = 0.0;
+        let mut amplitude = 0.0;
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let params = self
+                    .biome_at(x + dx * self.blend_distance, z + dz * self.blend_distance)
+                    .params();
+                base_height += params.base_height;
+                amplitude += params.amplitude;
+            }
+        }
+        (base_height / 9.0, amplitude / 9.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_is_deterministic() {
+        let (a, b) = (BiomeMap::new(42), BiomeMap::new(42));
+        for i in -50..50 {
+            let (x, z) = (i * 97, i * -53);
+            assert_eq!(a.biome_at(x, z), b.biome_at(x, z));
+        }
+    }
+
+    #[test]
+    fn every_biome_shows_up() {
+        let map = BiomeMap::new(1);
+        let mut found = Vec::new();
+        for z in (-8000..8000).step_by(64) {
+            for x in (-8000..8000).step_by(64) {
+                let biome = map.biome_at(x, z);
+                if !found.contains(&biome) {
+                    found.push(biome);
+                }
+            }
+        }
+        for biome in Biome::ALL {
+            assert!(found.contains(&biome), "{:?} never generated", biome);
+        }
+    }
+
+    #[test]
+    fn climate_picks_expected_biome() {
+        assert_eq!(Biome::from_climate(0.0, 0.8), Biome::Ocean);
+        assert_eq!(Biome::from_climate(-0.8, 0.0), Biome::Tundra);
+        assert_eq!(Biome::from_climate(0.8, -0.5), Biome::Desert);
+        assert_eq!(Biome::from_climate(0.0, -0.5), Biome::Mountains);
+        assert_eq!(Biome::from_climate(0.0, 0.0), Biome::Plains);
+    }
+
+    #[test]
+    fn borders_are_blended() {
+        let map = BiomeMap::new(7);
+        let max_step = {
+            let (mut max, mut min) = (f64::MIN, f64::MAX);
+            for biome in Biome::ALL {
+                max = max.max(biome.params().base_height);
+                min = min.min(biome.params().base_height);
+            }
+            //a single sample flipping changes the average by a ninth of the range
+            (max - min) / 9.0 + 1e-9
+        };
+        for x in -2000..2000 {
+            let (a, _) = map.blended_height_params(x, 300);
+            let (b, _) = map.blended_height_params(x + 1, 300);
+            assert!((a - b).abs() <= max_step * 3.0);
+        }
+    }
+}
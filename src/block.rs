@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use crate::chunk::FaceType;
+
+pub type BlockId = u16;
+
+//id 0 is always air, chunks are zero filled on creation
+pub const AIR: BlockId = 0;
+
+const BUILTIN_BLOCKS: &str = include_str!("../res/blocks.txt");
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub id: BlockId,
+    pub name: String,
+    pub solid: bool,
+    pub transparent: bool,
+    pub emissive: bool,
+    //indexed by FaceType
+    pub face_colors: [[f32; 3]; 6],
+}
+
+impl Block {
+    pub fn new(id: BlockId, name: &str) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            solid: true,
+            transparent: false,
+            emissive: false,
+            face_colors: [[1.0, 1.0, 1.0]; 6],
+        }
+    }
+
+    fn air() -> Self {
+        Self {
+            solid: false,
+            transparent: true,
+            ..Self::new(AIR, "air")
+        }
+    }
+
+    pub fn face_color(&self, face: FaceType) -> [f32; 3] {
+        self.face_colors[face as usize]
+    }
+}
+
+pub struct BlockRegistry {
+    blocks: Vec<Option<Block>>,
+    by_name: HashMap<String, BlockId>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        let mut registry = Self {
+            blocks: Vec::new(),
+            by_name: HashMap::new(),
+        };
+        registry.insert(Block::air());
+        registry
+    }
+
+    //the definitions compiled into the binary
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_BLOCKS).expect("builtin res/blocks.txt is invalid")
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("couldn't read {}: {}", path.display(), e))?;
+        Self::parse(&source).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+
+    //designers can edit the file next to the executable without recompiling,
+    //if it's missing or broken we still start with the builtin blocks
+    pub fn load_or_builtin(path: impl AsRef<std::path::Path>) -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let _ = path;
+                Self::builtin()
+            } else {
+                match Self::load(path) {
+                    Ok(registry) => registry,
+                    Err(e) => {
+                        log::warn!("falling back to builtin blocks: {}", e);
+                        Self::builtin()
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut registry = Self::new();
+        let mut current: Option<(Option<BlockId>, Block)> = None;
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if let Some((id, block)) = current.take() {
+                    registry.insert_parsed(id, block)?;
+                }
+                current = Some((None, Block::new(AIR, name.trim())));
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| anyhow::anyhow!("line {}: expected `key = value`", line_number))?;
+            let (id, block) = current.as_mut().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "line {}: `{}` outside of a [block] section",
+                    line_number,
+                    key
+                )
+            })?;
+
+            match key {
+                "id" => {
+                    *id = Some(value.parse().map_err(|_| {
+                        anyhow::anyhow!("line {}: invalid block id `{}`", line_number, value)
+                    })?)
+                }
+                "solid" => block.solid = parse_bool(value, line_number)?,
+                "transparent" => block.transparent = parse_bool(value, line_number)?,
+                "emissive" => block.emissive = parse_bool(value, line_number)?,
+                "color" => block.face_colors = [parse_color(value, line_number)?; 6],
+                "side" => {
+                    let color = parse_color(value, line_number)?;
+                    for face in [
+                        FaceType::Front,
+                        FaceType::Back,
+                        FaceType::Right,
+                        FaceType::Left,
+                    ] {
+                        block.face_colors[face as usize] = color;
+                    }
+                }
+                "front" => {
+                    block.face_colors[FaceType::Front as usize] = parse_color(value, line_number)?
+                }
+                "back" => {
+                    block.face_colors[FaceType::Back as usize] = parse_color(value, line_number)?
+                }
+                "right" => {
+                    block.face_colors[FaceType::Right as usize] = parse_color(value, line_number)?
+                }
+                "left" => {
+                    block.face_colors[FaceType::Left as usize] = parse_color(value, line_number)?
+                }
+                "top" => {
+                    block.face_colors[FaceType::Top as usize] = parse_color(value, line_number)?
+                }
+                "bottom" => {
+                    block.face_colors[FaceType::Bottom as usize] = parse_color(value, line_number)?
+                }
+                _ => anyhow::bail!("line {}: unknown key `{}`", line_number, key),
+            }
+        }
+        if let Some((id, block)) = current.take() {
+            registry.insert_parsed(id, block)?;
+        }
+
+        Ok(registry)
+    }
+
+    fn insert_parsed(&mut self, id: Option<BlockId>, mut block: Block) -> anyhow::Result<()> {
+        block.id = id.ok_or_else(|| anyhow::anyhow!("block `{}` has no id", block.name))?;
+        if block.id == AIR {
+            anyhow::bail!("block `{}` uses id 0 which is reserved for air", block.name);
+        }
+        if let Some(other) = self.blocks.get(block.id as usize).and_then(|b| b.as_ref()) {
+            anyhow::bail!(
+                "blocks `{}` and `{}` share id {}",
+                other.name,
+                block.name,
+                block.id
+            );
+        }
+        if self.by_name.contains_key(&block.name) {
+            anyhow::bail!("block `{}` is defined twice", block.name);
+        }
+        self.insert(block);
+        Ok(())
+    }
+
+    fn insert(&mut self, block: Block) {
+        let index = block.id as usize;
+        if self.blocks.len() <= index {
+            self.blocks.resize(index + 1, None);
+        }
+        self.by_name.insert(block.name.clone(), block.id);
+        self.blocks[index] = Some(block);
+    }
+
+    //unknown ids are treated like air so a stale chunk can't crash the mesher
+    pub fn get(&self, id: BlockId) -> &Block {
+        self.blocks
+            .get(id as usize)
+            .and_then(|b| b.as_ref())
+            .unwrap_or_else(|| self.blocks[AIR as usize].as_ref().unwrap())
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.by_name.get(name).copied()
+    }
+}
+
+fn parse_bool(value: &str, line_number: usize) -> anyhow::Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => anyhow::bail!(
+            "line {}: expected true or false, got `{}`",
+            line_number,
+            value
+        ),
+    }
+}
+
+fn parse_color(value: &str, line_number: usize) -> anyhow::Result<[f32; 3]> {
+    let channels = value
+        .split_whitespace()
+        .map(|c| c.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("line {}: invalid color `{}`", line_number, value))?;
+    match channels[..] {
+        [r, g, b] => Ok([r, g, b]),
+        _ => anyhow::bail!(
+            "line {}: color needs 3 channels, got `{}`",
+            line_number,
+            value
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the error message of a file that shouldn't parse
+    fn error(source: &str) -> String {
+        match BlockRegistry::parse(source) {
+            Ok(_) => panic!("parsed:\n{}", source),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_blocks_and_overrides() {
+        let registry = BlockRegistry::parse(
+            "# comment\n[glass]\nid = 4 # trailing\ntransparent = true\nsolid = false\n\
+             color = 1 1 1\nside = 0.5 0.5 0.5\ntop = 0 0 1\n",
+        )
+        .unwrap();
+        let glass = registry.get(registry.id("glass").unwrap());
+        assert_eq!(glass.id, 4);
+        assert!(glass.transparent && !glass.solid && !glass.emissive);
+        assert_eq!(glass.face_color(FaceType::Front), [0.5, 0.5, 0.5]);
+        assert_eq!(glass.face_color(FaceType::Top), [0.0, 0.0, 1.0]);
+        assert_eq!(glass.face_color(FaceType::Bottom), [1.0, 1.0, 1.0]);
+        //unknown ids read as air
+        assert_eq!(registry.get(99).id, AIR);
+        assert!(BlockRegistry::builtin().id("grass").is_some());
+    }
+
+    #[test]
+    fn rejects_broken_definitions() {
+        assert!(error("[stone]\ncolor = 1 1 1\n").contains("has no id"));
+        assert!(error("[stone]\nid = 0\n").contains("reserved for air"));
+        assert!(error("[stone]\nid = x\n").contains("line 2: invalid block id"));
+        assert!(error("[stone]\nid = 1\n[dirt]\nid = 1\n").contains("share id 1"));
+        assert!(error("[stone]\nid = 1\n[stone]\nid = 2\n").contains("defined twice"));
+        assert!(error("[stone]\nid = 1\nshiny = true\n").contains("line 3: unknown key `shiny`"));
+        assert!(error("[stone]\nid = 1\nsolid = yes\n").contains("expected true or false"));
+        assert!(error("[stone]\nid = 1\ncolor = 1 1\n").contains("needs 3 channels"));
+        assert!(error("[stone]\nid = 1\ntop = 1 1 1 1\n").contains("needs 3 channels"));
+        assert!(error("[stone]\nid = 1\ncolor = red\n").contains("invalid color"));
+        assert!(error("id = 1\n[stone]\n").contains("line 1: `id` outside of a [block] section"));
+        assert!(error("[stone]\nid 1\n").contains("expected `key = value`"));
+    }
+}
@@ -1,67 +1,65 @@
+use cgmath::Rad;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
+use crate::orientation::Orientation;
+
+#[derive(Clone, Copy)]
 pub struct Camera {
     pub camera_pos: cgmath::Point3<f32>,
-    pub camera_front: cgmath::Vector3<f32>,
+    pub orientation: Orientation,
+    //blocks per second
     pub speed: f32,
-    pub angular_speed: f32,
-    pub yaw: f32,
-    pub pitch: f32,
+    //how fast the arrow keys turn, per second
+    pub angular_speed: Rad<f32>,
 }
 
 impl Camera {
+    pub fn camera_front(&self) -> cgmath::Vector3<f32> {
+        self.orientation.forward()
+    }
+
     pub fn get_view(&self) -> cgmath::Matrix4<f32> {
         cgmath::Matrix4::look_at_rh(
             self.camera_pos,
-            self.camera_pos + self.camera_front,
+            self.camera_pos + self.camera_front(),
             (0.0, 1.0, 0.0).into(),
         )
     }
 
-    pub fn update_camera(&mut self, controller: &CameraController, dt: instant::Duration) {
-        use cgmath::InnerSpace;
-        let dt = dt.as_secs_f32();
-
-        if controller.is_right_arrow_pressed {
-            self.yaw += self.angular_speed * dt;
-        }
-        if controller.is_left_arrow_pressed {
-            self.yaw -= self.angular_speed * dt;
-        }
-        if controller.is_up_arrow_pressed {
-            self.pitch += self.angular_speed * dt;
-        }
-        if controller.is_down_arrow_pressed {
-            self.pitch -= self.angular_speed * dt;
-        }
-        let mut direction = cgmath::Vector3::<f32>::new(0.0, 0.0, 0.0);
-        direction.x = cgmath::Rad(self.yaw).0.cos() * cgmath::Rad(self.pitch).0.cos();
-        direction.y = cgmath::Rad(self.pitch).0.sin();
-        direction.z = cgmath::Rad(self.yaw).0.sin() * cgmath::Rad(self.pitch).0.cos();
-        self.camera_front = direction.normalize();
-
-        if controller.is_forward_pressed {
-            self.camera_pos += self.camera_front * self.speed * dt;
-        }
-        if controller.is_backward_pressed {
-            self.camera_pos -= self.camera_front * self.speed * dt;
+    //the camera between two ticks, t = 0 is self and t = 1 is next
+    pub fn interpolate(&self, next: &Camera, t: f32) -> Camera {
+        Camera {
+            camera_pos: self.camera_pos + (next.camera_pos - self.camera_pos) * t,
+            orientation: self.orientation.lerp(&next.orientation, t),
+            ..*next
         }
+    }
 
-        let right = self.camera_front.cross((0.0, 1.0, 0.0).into()).normalize();
-        if controller.is_right_pressed {
-            self.camera_pos += right * self.speed * dt;
-        }
-        if controller.is_left_pressed {
-            self.camera_pos -= right * self.speed * dt;
-        }
+    //turns the camera with the mouse and arrow keys, where it moves to is up
+    //to the camera mode
+    pub fn look(&mut self, controller: &mut CameraController, dt: instant::Duration) {
+        let dt = dt.as_secs_f32();
 
-        let camera_up = right.cross(self.camera_front);
-        if controller.is_up_pressed {
-            self.camera_pos += camera_up * self.speed * dt;
-        }
-        if controller.is_down_pressed {
-            self.camera_pos -= camera_up * self.speed * dt;
-        }
+        //mouse motion is in pixels, not scaled by dt
+        let (dx, dy) = controller.take_mouse_delta();
+        let y_sign = if controller.invert_y { -1.0 } else { 1.0 };
+        self.orientation.rotate(
+            controller.sensitivity * dx,
+            controller.sensitivity * -dy * y_sign,
+        );
+
+        let turn = self.angular_speed * dt;
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        self.orientation.rotate(
+            turn * axis(
+                controller.is_right_arrow_pressed,
+                controller.is_left_arrow_pressed,
+            ),
+            turn * axis(
+                controller.is_up_arrow_pressed,
+                controller.is_down_arrow_pressed,
+            ),
+        );
     }
 }
 
@@ -76,6 +74,12 @@ pub struct CameraController {
     pub is_left_arrow_pressed: bool,
     pub is_up_arrow_pressed: bool,
     pub is_down_arrow_pressed: bool,
+    //per pixel of mouse motion
+    pub sensitivity: Rad<f32>,
+    //moving the mouse up looks down
+    pub invert_y: bool,
+    //motion since the last camera update
+    mouse_delta: (f32, f32),
 }
 
 impl CameraController {
@@ -91,8 +95,22 @@ impl CameraController {
             is_left_arrow_pressed: false,
             is_up_arrow_pressed: false,
             is_down_arrow_pressed: false,
+            sensitivity: Rad(0.002),
+            invert_y: false,
+            mouse_delta: (0.0, 0.0),
         }
     }
+
+    //raw DeviceEvent::MouseMotion deltas, only fed in while the cursor is grabbed
+    pub fn process_mouse(&mut self, dx: f64, dy: f64) {
+        self.mouse_delta.0 += dx as f32;
+        self.mouse_delta.1 += dy as f32;
+    }
+
+    fn take_mouse_delta(&mut self) -> (f32, f32) {
+        std::mem::take(&mut self.mouse_delta)
+    }
+
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
         match event {
             WindowEvent::KeyboardInput {
@@ -176,3 +194,79 @@ impl Projection {
         cgmath::perspective(cgmath::Deg(self.fov), self.aspect, self.znear, self.zfar)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::MAX_PITCH;
+    use cgmath::{Deg, InnerSpace};
+
+    fn camera() -> Camera {
+        Camera {
+            camera_pos: (0.0, 0.0, 0.0).into(),
+            orientation: Orientation::new(Deg(0.0), Deg(0.0)),
+            speed: 5.0,
+            angular_speed: Rad(2.0),
+        }
+    }
+
+    //compares where the camera looks, the angles can be off by rounding
+    fn assert_looks(camera: &Camera, yaw: impl Into<Rad<f32>>, pitch: impl Into<Rad<f32>>) {
+        let expected = Orientation::new(yaw, pitch).forward();
+        let front = camera.orientation.forward();
+        assert!(
+            (front - expected).magnitude() < 1e-5,
+            "{:?} != {:?}",
+            front,
+            expected
+        );
+    }
+
+    #[test]
+    fn mouse_motion_turns_the_camera() {
+        let mut camera = camera();
+        let mut controller = CameraController::new();
+        controller.process_mouse(100.0, 0.0);
+        controller.process_mouse(50.0, -25.0);
+        camera.look(&mut controller, instant::Duration::ZERO);
+        let yaw = controller.sensitivity * 150.0;
+        //moving the mouse up looks up
+        assert_looks(&camera, yaw, controller.sensitivity * 25.0);
+
+        //the motion is used up
+        camera.look(&mut controller, instant::Duration::ZERO);
+        assert_looks(&camera, yaw, controller.sensitivity * 25.0);
+
+        controller.invert_y = true;
+        controller.process_mouse(0.0, -25.0);
+        camera.look(&mut controller, instant::Duration::ZERO);
+        assert_looks(&camera, yaw, Rad(0.0));
+    }
+
+    #[test]
+    fn pitch_stops_before_flipping() {
+        let mut camera = camera();
+        let mut controller = CameraController::new();
+        controller.process_mouse(0.0, -1.0e6);
+        camera.look(&mut controller, instant::Duration::ZERO);
+        assert_eq!(camera.orientation, Orientation::new(Rad(0.0), MAX_PITCH));
+        assert!(camera.camera_front().y < 1.0 && camera.camera_front().y > 0.99);
+
+        controller.is_down_arrow_pressed = true;
+        camera.look(&mut controller, instant::Duration::from_secs(100));
+        assert_eq!(camera.orientation, Orientation::new(Rad(0.0), -MAX_PITCH));
+    }
+
+    #[test]
+    fn interpolation_blends_two_ticks() {
+        let previous = camera();
+        let mut next = camera();
+        next.camera_pos = (2.0, 0.0, -4.0).into();
+        next.orientation.rotate(Deg(90.0), Deg(30.0));
+
+        let half = previous.interpolate(&next, 0.5);
+        assert_eq!(half.camera_pos, (1.0, 0.0, -2.0).into());
+        assert_looks(&half, Deg(45.0), Deg(15.0));
+        assert_eq!(previous.interpolate(&next, 1.0).camera_pos, next.camera_pos);
+    }
+}
@@ -0,0 +1,230 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::block::AIR;
+use crate::camera::{Camera, CameraController};
+use crate::player::{self, Player, PlayerInput};
+use crate::raycast;
+use crate::world::World;
+
+//decides where the camera is every frame. the orientation is already
+//updated from the mouse and arrow keys when update runs
+pub trait CameraMode {
+    fn name(&self) -> &'static str;
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        player: &mut Player,
+        controller: &CameraController,
+        world: &World,
+        dt: f32,
+    );
+}
+
+//every camera mode, switched through in this order
+pub fn all() -> Vec<Box<dyn CameraMode>> {
+    vec![
+        Box::new(FreeFly),
+        Box::new(FirstPerson),
+        Box::new(ThirdPerson { distance: 4.0 }),
+    ]
+}
+
+//flies through everything, the player body is carried along so
+//switching to a walking mode drops it where the camera is
+pub struct FreeFly;
+
+impl CameraMode for FreeFly {
+    fn name(&self) -> &'static str {
+        "free fly"
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        player: &mut Player,
+        controller: &CameraController,
+        _: &World,
+        dt: f32,
+    ) {
+        let front = camera.camera_front();
+        if controller.is_forward_pressed {
+            camera.camera_pos += front * camera.speed * dt;
+        }
+        if controller.is_backward_pressed {
+            camera.camera_pos -= front * camera.speed * dt;
+        }
+
+        let right = camera.orientation.right();
+        if controller.is_right_pressed {
+            camera.camera_pos += right * camera.speed * dt;
+        }
+        if controller.is_left_pressed {
+            camera.camera_pos -= right * camera.speed * dt;
+        }
+
+        let camera_up = camera.orientation.up();
+        if controller.is_up_pressed {
+            camera.camera_pos += camera_up * camera.speed * dt;
+        }
+        if controller.is_down_pressed {
+            camera.camera_pos -= camera_up * camera.speed * dt;
+        }
+
+        *player = Player::new(camera.camera_pos - Vector3::new(0.0, player::EYE_HEIGHT, 0.0));
+    }
+}
+
+//looks out of the player's eyes
+pub struct FirstPerson;
+
+impl CameraMode for FirstPerson {
+    fn name(&self) -> &'static str {
+        "first person"
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        player: &mut Player,
+        controller: &CameraController,
+        world: &World,
+        dt: f32,
+    ) {
+        walk(camera, player, controller, world, dt);
+        camera.camera_pos = player.eye();
+    }
+}
+
+//orbits the player at a distance, moves closer when terrain is in the way
+pub struct ThirdPerson {
+    pub distance: f32,
+}
+
+//how far the camera stays away from the block it would clip into
+const ORBIT_MARGIN: f32 = 0.2;
+
+impl CameraMode for ThirdPerson {
+    fn name(&self) -> &'static str {
+        "third person"
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        player: &mut Player,
+        controller: &CameraController,
+        world: &World,
+        dt: f32,
+    ) {
+        walk(camera, player, controller, world, dt);
+
+        let eye = player.eye();
+        let back = -camera.camera_front();
+        let solid = |pos| {
+            let block = world.get_block(pos);
+            if world.registry().get(block).solid {
+                block
+            } else {
+                AIR
+            }
+        };
+        let distance = match raycast::raycast(eye, back, self.distance, solid) {
+            Some(hit) => (hit.distance - ORBIT_MARGIN).max(0.0),
+            None => self.distance,
+        };
+        camera.camera_pos = eye + back * distance;
+    }
+}
+
+//moves the player on the ground plane in the direction the camera looks
+fn walk(
+    camera: &Camera,
+    player: &mut Player,
+    controller: &CameraController,
+    world: &World,
+    dt: f32,
+) {
+    let right = camera.orientation.right();
+    let forward = Vector3::new(right.z, 0.0, -right.x);
+    let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+    let mut walk = forward
+        * axis(
+            controller.is_forward_pressed,
+            controller.is_backward_pressed,
+        )
+        + right * axis(controller.is_right_pressed, controller.is_left_pressed);
+    if walk.magnitude2() > 0.0 {
+        walk = walk.normalize() * player::WALK_SPEED;
+    }
+    let input = PlayerInput {
+        walk,
+        jump: controller.is_up_pressed,
+    };
+    player.update(input, |pos| world.is_solid(pos), dt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::{ChunkPos, WorldPos};
+    use crate::orientation::Orientation;
+    use crate::world::tests::world;
+    use cgmath::{Deg, Point3, Rad};
+
+    fn camera() -> Camera {
+        Camera {
+            camera_pos: (0.0, 0.0, 0.0).into(),
+            //looking along +x
+            orientation: Orientation::new(Deg(0.0), Deg(0.0)),
+            speed: 5.0,
+            angular_speed: Rad(2.0),
+        }
+    }
+
+    //a floor at y = 0 with the player standing on it
+    fn setup() -> (World, Player) {
+        let mut world = world(&[ChunkPos::new(0, 0, 0)]);
+        let stone = world.registry().id("stone").unwrap();
+        for x in 0..16 {
+            for z in 0..16 {
+                world.set_block(WorldPos::new(x, 0, z), stone);
+            }
+        }
+        (world, Player::new(Point3::new(8.5, 1.0, 8.5)))
+    }
+
+    #[test]
+    fn first_person_walks_where_the_camera_looks() {
+        let (world, mut player) = setup();
+        let mut camera = camera();
+        //looking down doesn't slow walking down
+        camera.orientation.rotate(Deg(0.0), Deg(-60.0));
+        let mut controller = CameraController::new();
+        controller.is_forward_pressed = true;
+
+        FirstPerson.update(&mut camera, &mut player, &controller, &world, 0.5);
+        assert!(player.on_ground);
+        assert!((player.position.x - (8.5 + player::WALK_SPEED * 0.5)).abs() < 1e-5);
+        assert_eq!(player.position.y, 1.0);
+        assert_eq!(camera.camera_pos, player.eye());
+    }
+
+    #[test]
+    fn third_person_stays_out_of_walls() {
+        let (mut world, mut player) = setup();
+        let mut camera = camera();
+        let controller = CameraController::new();
+        let mut mode = ThirdPerson { distance: 4.0 };
+
+        mode.update(&mut camera, &mut player, &controller, &world, 0.0);
+        assert!((camera.camera_pos.x - (8.5 - 4.0)).abs() < 1e-5);
+
+        //a wall right behind the player
+        let stone = world.registry().id("stone").unwrap();
+        world.set_block(WorldPos::new(6, 2, 8), stone);
+        mode.update(&mut camera, &mut player, &controller, &world, 0.0);
+        assert!((camera.camera_pos.x - (7.0 + ORBIT_MARGIN)).abs() < 1e-5);
+        assert!(!world.is_solid(WorldPos::from_point(camera.camera_pos)));
+    }
+}
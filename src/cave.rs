@@ -0,0 +1,299 @@
+use std::f64::consts::TAU;
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::block::{BlockId, AIR};
+use crate::chunk::ChunkMeshData;
+use crate::noise::{self, Perlin};
+
+//worms start in 16x16 columns of the world so a chunk only has to look
+//at the cells around it to find every worm that can reach it. not tied to
+//the chunk size so changing it doesn't move the caves
+const WORM_CELL_WIDTH: i32 = 16;
+const WORM_CELL_DEPTH: i32 = 16;
+
+//carves two kinds of caves out of already generated terrain:
+//- cheese caves, big open caverns where the 3d noise is high enough
+//- worm tunnels, paths that wander through the noise field
+//everything is sampled in world space so caves line up between chunks
+pub struct CaveCarver {
+    seed: u64,
+    cheese: Perlin,
+    worm: Perlin,
+    //0..1, how much of the underground becomes caverns, 0 disables them
+    pub cheese_density: f64,
+    //in blocks, bigger means bigger caverns
+    pub cheese_scale: f64,
+    //0..1, chance for every worm cell to start a worm
+    pub worm_density: f64,
+    //in blocks
+    pub worm_length: u32,
+    pub worm_radius: f64,
+    //in blocks, bigger means straighter tunnels
+    pub worm_scale: f64,
+    //world heights caves are carved between (both inclusive)
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            cheese: Perlin::new(seed ^ 0xCAFE_F00D),
+            worm: Perlin::new(seed ^ 0x0BAD_5EED),
+            cheese_density: 0.15,
+            cheese_scale: 24.0,
+            worm_density: 0.5,
+            worm_length: 48,
+            worm_radius: 1.6,
+            worm_scale: 16.0,
+            min_y: 1,
+            max_y: 10,
+        }
+    }
+
+    //turns every cave block of the chunk into air, can_carve gets the
+    //local position and the block that is there right now
+    pub fn carve(
+        &self,
+        chunk: &mut ChunkMeshData,
+        can_carve: impl Fn(usize, usize, usize, BlockId) -> bool,
+    ) {
+        let origin = chunk.origin();
+        let size = chunk.size();
+        let size = Vector3::new(size.width, size.height, size.depth)
+            .cast::<i32>()
+            .unwrap();
+        let min_y = self.min_y.max(origin.y);
+        let max_y = self.max_y.min(origin.y + size.y - 1);
+        if min_y > max_y {
+            return;
+        }
+
+        let mut carve = |x: i32, y: i32, z: i32| {
+            let (x, y, z) = (
+                (x - origin.x) as usize,
+                (y - origin.y) as usize,
+                (z - origin.z) as usize,
+            );
+            let block = chunk.get_block(x, y, z);
+            if block != AIR && can_carve(x, y, z, block) {
+                chunk.set_block(x, y, z, AIR);
+            }
+        };
+
+        if self.cheese_density > 0.0 {
+            for z in origin.z..origin.z + size.z {
+                for y in min_y..=max_y {
+                    for x in origin.x..origin.x + size.x {
+                        if self.is_cheese(x, y, z) {
+                            carve(x, y, z);
+                        }
+                    }
+                }
+            }
+        }
+
+        let min = Vector3::new(origin.x, min_y, origin.z);
+        let max = Vector3::new(origin.x + size.x - 1, max_y, origin.z + size.z - 1);
+        self.for_each_worm_point(min, max, |point, radius| {
+            let reach = radius.ceil() as i32;
+            let center = point.map(|c| c.floor() as i32);
+            for z in (center.z - reach).max(min.z)..=(center.z + reach).min(max.z) {
+                for y in (center.y - reach).max(min.y)..=(center.y + reach).min(max.y) {
+                    for x in (center.x - reach).max(min.x)..=(center.x + reach).min(max.x) {
+                        if in_sphere(point, radius, x, y, z) {
+                            carve(x, y, z);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn is_cheese(&self, x: i32, y: i32, z: i32) -> bool {
+        if self.cheese_density <= 0.0 {
+            return false;
+        }
+        //squashed vertically so caverns end up wider than they are tall
+        let value = self.cheese.get(
+            x as f64 / self.cheese_scale,
+            y as f64 * 2.0 / self.cheese_scale,
+            z as f64 / self.cheese_scale,
+        );
+        //the noise is mostly close to 0, so densities near 1 already
+        //carve out most of the underground
+        value > 0.6 * (1.0 - self.cheese_density)
+    }
+
+    //calls f with the center and radius of every worm segment that can
+    //touch the blocks between min and max
+    fn for_each_worm_point(
+        &self,
+        min: Vector3<i32>,
+        max: Vector3<i32>,
+        mut f: impl FnMut(Vector3<f64>, f64),
+    ) {
+        if self.worm_density <= 0.0 || self.worm_length == 0 {
+            return;
+        }
+        let reach = self.worm_length as i32 + self.worm_radius.ceil() as i32;
+        let cells_x = (min.x - reach).div_euclid(WORM_CELL_WIDTH)
+            ..=(max.x + reach).div_euclid(WORM_CELL_WIDTH);
+        let cells_z = (min.z - reach).div_euclid(WORM_CELL_DEPTH)
+            ..=(max.z + reach).div_euclid(WORM_CELL_DEPTH);
+
+        for cell_z in cells_z {
+            for cell_x in cells_x.clone() {
+                self.walk_worm(cell_x, cell_z, min, max, &mut f);
+            }
+        }
+    }
+
+    fn walk_worm(
+        &self,
+        cell_x: i32,
+        cell_z: i32,
+        min: Vector3<i32>,
+        max: Vector3<i32>,
+        f: &mut impl FnMut(Vector3<f64>, f64),
+    ) {
+        let hash = |i| noise::position_hash(self.seed ^ 0x7E57_CA7E, cell_x, i, cell_z);
+        if hash(0) >= self.worm_density {
+            return;
+        }
+
+        let mut position = Vector3::new(
+            (cell_x as f64 + hash(1)) * WORM_CELL_WIDTH as f64,
+            self.min_y as f64 + hash(2) * (self.max_y - self.min_y) as f64,
+            (cell_z as f64 + hash(3)) * WORM_CELL_DEPTH as f64,
+        );
+        let mut yaw = hash(4) * TAU;
+        let radius = self.worm_radius;
+
+        for _ in 0..self.worm_length {
+            let near = |min: i32, max: i32, c: f64| {
+                c + radius >= min as f64 && c - radius <= max as f64 + 1.0
+            };
+            if near(min.x, max.x, position.x)
+                && near(min.y, max.y, position.y)
+                && near(min.z, max.z, position.z)
+            {
+                f(position, radius);
+            }
+
+            //the noise steers the worm, offset on every axis so yaw and
+            //pitch don't follow the same values
+            let sample = position / self.worm_scale;
+            yaw += self.worm.get(sample.x, sample.y, sample.z) * 0.5;
+            let pitch = self
+                .worm
+                .get(sample.x + 31.7, sample.y + 47.3, sample.z + 11.9)
+                * 0.6;
+            let direction = Vector3::new(
+                yaw.cos() * pitch.cos(),
+                pitch.sin(),
+                yaw.sin() * pitch.cos(),
+            );
+            position += direction.normalize();
+            //tunnels stay inside the depth range
+            position.y = position.y.clamp(self.min_y as f64, self.max_y as f64);
+        }
+    }
+}
+
+fn in_sphere(center: Vector3<f64>, radius: f64, x: i32, y: i32, z: i32) -> bool {
+    let block_center = Vector3::new(x as f64 + 0.5, y as f64 + 0.5, z as f64 + 0.5);
+    (block_center - center).magnitude2() <= radius * radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::{ChunkPos, ChunkSize};
+
+    const SIZE: ChunkSize = ChunkSize::DEFAULT;
+
+    //what carve should do to a single block in world space, worked out
+    //block by block instead of worm by worm
+    fn is_cave(caves: &CaveCarver, x: i32, y: i32, z: i32) -> bool {
+        if y < caves.min_y || y > caves.max_y {
+            return false;
+        }
+        if caves.is_cheese(x, y, z) {
+            return true;
+        }
+        let position = Vector3::new(x, y, z);
+        let mut cave = false;
+        caves.for_each_worm_point(position, position, |point, radius| {
+            cave |= in_sphere(point, radius, x, y, z);
+        });
+        cave
+    }
+
+    fn solid_chunk(position: ChunkPos) -> ChunkMeshData {
+        let mut chunk = ChunkMeshData::new(position, SIZE);
+        for z in 0..SIZE.depth {
+            for y in 0..SIZE.height {
+                for x in 0..SIZE.width {
+                    chunk.set_block(x, y, z, 1);
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn chunks_agree_with_world_space_caves() {
+        let caves = CaveCarver::new(3);
+        let mut carved = 0;
+        //two chunks sharing a border
+        for cx in -1..=0 {
+            let mut chunk = solid_chunk(ChunkPos::new(cx, 0, 0));
+            let origin = chunk.origin();
+            caves.carve(&mut chunk, |_, _, _, _| true);
+            for z in 0..SIZE.depth {
+                for y in 0..=caves.max_y as usize {
+                    for x in 0..SIZE.width {
+                        let world = origin + Vector3::new(x, y, z).cast().unwrap();
+                        let cave = is_cave(&caves, world.x, world.y, world.z);
+                        assert_eq!(chunk.get_block(x, y, z) == AIR, cave, "{:?}", world);
+                        carved += cave as u32;
+                    }
+                }
+            }
+        }
+        assert!(carved > 0, "no caves in 2 chunks");
+    }
+
+    #[test]
+    fn caves_stay_in_depth_range() {
+        let mut caves = CaveCarver::new(5);
+        caves.cheese_density = 0.9;
+        caves.worm_density = 1.0;
+        caves.min_y = 4;
+        caves.max_y = 7;
+        let mut chunk = solid_chunk(ChunkPos::new(0, 0, 0));
+        caves.carve(&mut chunk, |_, _, _, _| true);
+        for z in 0..SIZE.depth {
+            for y in 0..SIZE.height {
+                for x in 0..SIZE.width {
+                    if !(4..=7).contains(&y) {
+                        assert_ne!(chunk.get_block(x, y, z), AIR);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_density_carves_nothing() {
+        let mut caves = CaveCarver::new(9);
+        caves.cheese_density = 0.0;
+        caves.worm_density = 0.0;
+        let mut chunk = solid_chunk(ChunkPos::new(0, 0, 0));
+        caves.carve(&mut chunk, |_, _, _, _| true);
+        assert!(chunk.chunk_data.iter().all(|block| block == 1));
+    }
+}
@@ -1,64 +1,75 @@
 use wgpu::util::DeviceExt;
 
-pub const CHUNK_WIDTH: usize = 16;
-pub const CHUNK_DEPTH: usize = 16;
-pub const CHUNK_HEIGHT: usize = 16;
-const BLOCK_SIZE: f32 = 1.0;
-
-const FRONT_COLOR: [f32; 3] = [0.71, 1.0, 0.34];
-
-const BACK_COLOR: [f32; 3] = FRONT_COLOR;
-const RIGHT_COLOR: [f32; 3] = FRONT_COLOR;
-const LEFT_COLOR: [f32; 3] = FRONT_COLOR;
-const TOP_COLOR: [f32; 3] = FRONT_COLOR;
-const BOTTOM_COLOR: [f32; 3] = FRONT_COLOR;
+use crate::block::{Block, BlockId, BlockRegistry, AIR};
+use crate::coords::{ChunkPos, ChunkSize, LocalPos, WorldPos};
+use crate::palette::PaletteStorage;
 
-/*
-const BACK_COLOR: [f32; 3] = [0.4, 1.0, 1.0];
-const RIGHT_COLOR: [f32; 3] = [1.0, 0.53, 0.43]; //orang
-const LEFT_COLOR: [f32; 3] = [0.82, 0.32, 1.0];
-const TOP_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
-const BOTTOM_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
-*/
+const BLOCK_SIZE: f32 = 1.0;
 
-const MAX_VOXEL_COUNT_PER_CHUNK: usize = CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_DEPTH;
 //const VERTEX_PER_VOXEL: usize = 36;
-//const MAX_VERTEX_PER_CHUNK: usize = VERTEX_PER_VOXEL * MAX_VOXEL_COUNT_PER_CHUNK;
-//const MAX_INDEX_COUNT_PER_CHUNK: usize = MAX_VERTEX_PER_CHUNK;
 
 pub struct ChunkMeshData {
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
     num_of_faces: u32,
-    pub chunk_data: Vec<u8>, //storing local coordinates
-    world_coordinates: cgmath::Vector3<usize>,
+    pub chunk_data: PaletteStorage, //storing local coordinates
+    position: ChunkPos,
+    size: ChunkSize,
+    //set when the mesh changed and the gpu buffers are out of date
+    dirty: bool,
+    buffers: Option<ChunkBuffers>,
 }
 
-//might be wrong (oopsies)
-fn to_1d_array(x: usize, y: usize, z: usize) -> usize {
-    y + x * CHUNK_HEIGHT + (z * CHUNK_WIDTH * CHUNK_HEIGHT)
+//kept alive between frames, rewritten in place if the new mesh fits
+pub struct ChunkBuffers {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
 }
 
 impl ChunkMeshData {
-    pub fn new(world_coordinates: cgmath::Vector3<usize>) -> Self {
-        let chunk_data = vec![0; MAX_VOXEL_COUNT_PER_CHUNK];
+    pub fn new(position: ChunkPos, size: ChunkSize) -> Self {
+        let chunk_data = PaletteStorage::new(size.volume(), AIR);
 
         Self {
             vertices: Vec::new(),
             indices: Vec::new(),
             num_of_faces: 0,
             chunk_data,
-            world_coordinates,
+            position,
+            size,
+            dirty: false,
+            buffers: None,
         }
     }
 
-    fn generate_mesh_face_data(&mut self, x: usize, y: usize, z: usize, face_type: FaceType) {
+    //size is the extent of the quad in blocks along x, y and z,
+    //the axis the face points along is always 1
+    fn generate_mesh_face_data(
+        &mut self,
+        (x, y, z): (usize, usize, usize),
+        size: [usize; 3],
+        face_type: FaceType,
+        block: &Block,
+        color_seed: Option<u64>,
+    ) {
+        let origin = self.origin();
+        let shade = match color_seed {
+            Some(seed) => face_shade(
+                seed,
+                self.position.world_pos(LocalPos::new(x, y, z), self.size),
+                face_type,
+            ),
+            None => 1.0,
+        };
         let vertex_face = generate_voxel_face(
-            x as f32,
-            y as f32,
-            z as f32,
-            &self.world_coordinates,
+            x as f32 + origin.x as f32,
+            y as f32 + origin.y as f32,
+            z as f32 + origin.z as f32,
+            size.map(|s| s as f32),
             face_type,
+            block,
+            shade,
         );
         for vertex in vertex_face.iter() {
             self.vertices.push(*vertex);
@@ -70,77 +81,282 @@ impl ChunkMeshData {
         self.num_of_faces += 1;
     }
 
-    pub fn generate_data(&mut self) {
-        for y in 0..CHUNK_HEIGHT {
-            for z in y..CHUNK_DEPTH {
-                for x in 0..CHUNK_WIDTH {
-                    self.chunk_data[to_1d_array(x, y, z)] = 1;
+    pub fn position(&self) -> ChunkPos {
+        self.position
+    }
+
+    pub fn size(&self) -> ChunkSize {
+        self.size
+    }
+
+    //world space position of the chunk's (0, 0, 0) voxel
+    pub fn origin(&self) -> WorldPos {
+        self.position.origin(self.size)
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        LocalPos::new(x, y, z).index(self.size)
+    }
+
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> BlockId {
+        self.chunk_data.get(self.index(x, y, z))
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        self.chunk_data.set(self.index(x, y, z), block);
+    }
+
+    //the neighbour coordinates are local and can be one block outside of
+    //the chunk, those get resolved by `outside` (usually the World)
+    fn neighbour(
+        &self,
+        x: usize,
+        y: usize,
+        z: usize,
+        face_type: FaceType,
+        outside: &impl Fn(i32, i32, i32) -> BlockId,
+    ) -> BlockId {
+        let (nx, ny, nz) = face_type.offset();
+        let (nx, ny, nz) = (x as i32 + nx, y as i32 + ny, z as i32 + nz);
+        if self.size.contains(nx, ny, nz) {
+            self.get_block(nx as usize, ny as usize, nz as usize)
+        } else {
+            outside(nx, ny, nz)
+        }
+    }
+
+    //a face is hidden only behind an opaque block or the same
+    //transparent block (no faces between two glass blocks)
+    fn is_face_visible(
+        &self,
+        (x, y, z): (usize, usize, usize),
+        face_type: FaceType,
+        block: &Block,
+        registry: &BlockRegistry,
+        outside: &impl Fn(i32, i32, i32) -> BlockId,
+    ) -> bool {
+        let neighbour = self.neighbour(x, y, z, face_type, outside);
+        neighbour != block.id && registry.get(neighbour).transparent
+    }
+
+    pub fn generate_mesh(
+        &mut self,
+        registry: &BlockRegistry,
+        options: MeshOptions,
+        outside: impl Fn(i32, i32, i32) -> BlockId,
+    ) -> u32 {
+        self.vertices.clear();
+        self.indices.clear();
+        self.num_of_faces = 0;
+        self.dirty = true;
+        //nothing to draw in the sky
+        if self.chunk_data.single_value() == Some(AIR) {
+            return 0;
+        }
+
+        match options.mode {
+            MeshingMode::Naive => self.generate_naive_mesh(registry, options.color_seed, &outside),
+            MeshingMode::Greedy => {
+                for face_type in FaceType::ALL {
+                    self.generate_greedy_faces(registry, face_type, options.color_seed, &outside);
                 }
             }
         }
+        self.num_of_faces
+        //println!("Number of faces {}", self.num_of_faces);
     }
 
-    pub fn generate_mesh(&mut self) -> u32 {
-        for y in 0..CHUNK_HEIGHT {
-            for z in y..CHUNK_DEPTH {
-                for x in 0..CHUNK_WIDTH {
-                    if z == CHUNK_DEPTH - 1 || self.chunk_data[to_1d_array(x, y, z + 1)] != 1 {
-                        self.generate_mesh_face_data(x, y, z, FaceType::Front);
-                    }
-                    if z == 0 || self.chunk_data[to_1d_array(x, y, z - 1)] != 1 {
-                        self.generate_mesh_face_data(x, y, z, FaceType::Back);
+    //one quad for every visible voxel face
+    fn generate_naive_mesh(
+        &mut self,
+        registry: &BlockRegistry,
+        color_seed: Option<u64>,
+        outside: &impl Fn(i32, i32, i32) -> BlockId,
+    ) {
+        for y in 0..self.size.height {
+            for z in 0..self.size.depth {
+                for x in 0..self.size.width {
+                    let block = registry.get(self.get_block(x, y, z));
+                    if block.id == AIR {
+                        continue;
                     }
-                    if x == CHUNK_WIDTH - 1 || self.chunk_data[to_1d_array(x + 1, y, z)] != 1 {
-                        self.generate_mesh_face_data(x, y, z, FaceType::Right);
+                    for face_type in FaceType::ALL {
+                        if self.is_face_visible((x, y, z), face_type, block, registry, outside) {
+                            self.generate_mesh_face_data(
+                                (x, y, z),
+                                [1, 1, 1],
+                                face_type,
+                                block,
+                                color_seed,
+                            );
+                        }
                     }
-                    if x == 0 || self.chunk_data[to_1d_array(x - 1, y, z)] != 1 {
-                        self.generate_mesh_face_data(x, y, z, FaceType::Left);
+                }
+            }
+        }
+    }
+
+    //goes through the chunk slice by slice along the face normal and merges
+    //neighbouring visible faces of the same block into rectangles
+    fn generate_greedy_faces(
+        &mut self,
+        registry: &BlockRegistry,
+        face_type: FaceType,
+        color_seed: Option<u64>,
+        outside: &impl Fn(i32, i32, i32) -> BlockId,
+    ) {
+        let dims = [self.size.width, self.size.height, self.size.depth];
+        let normal = face_type.axis();
+        let (u, v) = ((normal + 1) % 3, (normal + 2) % 3);
+        let mut mask: Vec<Option<BlockId>> = vec![None; dims[u] * dims[v]];
+
+        for slice in 0..dims[normal] {
+            for j in 0..dims[v] {
+                for i in 0..dims[u] {
+                    let mut pos = [0; 3];
+                    pos[normal] = slice;
+                    pos[u] = i;
+                    pos[v] = j;
+                    let pos = (pos[0], pos[1], pos[2]);
+                    let block = registry.get(self.get_block(pos.0, pos.1, pos.2));
+                    let visible = block.id != AIR
+                        && self.is_face_visible(pos, face_type, block, registry, outside);
+                    mask[i + j * dims[u]] = if visible { Some(block.id) } else { None };
+                }
+            }
+
+            for j in 0..dims[v] {
+                let mut i = 0;
+                while i < dims[u] {
+                    let Some(id) = mask[i + j * dims[u]] else {
+                        i += 1;
+                        continue;
+                    };
+                    let mut width = 1;
+                    while i + width < dims[u] && mask[i + width + j * dims[u]] == Some(id) {
+                        width += 1;
                     }
-                    if y == CHUNK_HEIGHT - 1 || self.chunk_data[to_1d_array(x, y + 1, z)] != 1 {
-                        self.generate_mesh_face_data(x, y, z, FaceType::Top);
+                    let mut height = 1;
+                    while j + height < dims[v]
+                        && (i..i + width).all(|k| mask[k + (j + height) * dims[u]] == Some(id))
+                    {
+                        height += 1;
                     }
-                    if y == 0 || self.chunk_data[to_1d_array(x, y - 1, z)] != 1 {
-                        self.generate_mesh_face_data(x, y, z, FaceType::Bottom);
+                    for row in j..j + height {
+                        for k in i..i + width {
+                            mask[k + row * dims[u]] = None;
+                        }
                     }
+
+                    let mut pos = [0; 3];
+                    pos[normal] = slice;
+                    pos[u] = i;
+                    pos[v] = j;
+                    let mut size = [1; 3];
+                    size[u] = width;
+                    size[v] = height;
+                    self.generate_mesh_face_data(
+                        (pos[0], pos[1], pos[2]),
+                        size,
+                        face_type,
+                        registry.get(id),
+                        color_seed,
+                    );
+                    i += width;
                 }
             }
         }
-        self.num_of_faces
-        //println!("Number of faces {}", self.num_of_faces);
     }
 
-    pub fn build(&mut self, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer, u32) {
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex buffer"),
-            contents: bytemuck::cast_slice(&self.vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("index buffer"),
-            contents: bytemuck::cast_slice(&self.indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
+    //copy of the voxel data without the mesh, used to mesh on another thread
+    pub fn snapshot(&self) -> Self {
+        Self {
+            chunk_data: self.chunk_data.clone(),
+            ..Self::new(self.position, self.size)
+        }
+    }
+
+    pub fn take_mesh(&mut self) -> (Vec<Vertex>, Vec<u32>) {
+        self.num_of_faces = 0;
         (
-            vertex_buffer,
-            index_buffer,
-            self.indices.len().try_into().unwrap(),
+            std::mem::take(&mut self.vertices),
+            std::mem::take(&mut self.indices),
         )
     }
+
+    pub fn set_mesh(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        self.num_of_faces = (vertices.len() / 4) as u32;
+        self.vertices = vertices;
+        self.indices = indices;
+        self.dirty = true;
+    }
+
+    //uploads the mesh if it changed since the last call,
+    //returns how many bytes were written to the gpu
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> u64 {
+        if !self.dirty {
+            return 0;
+        }
+        self.dirty = false;
+
+        if self.indices.is_empty() {
+            self.buffers = None;
+            return 0;
+        }
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&self.vertices);
+        let index_bytes: &[u8] = bytemuck::cast_slice(&self.indices);
+        let index_count = self.indices.len().try_into().unwrap();
+
+        match self.buffers.as_mut() {
+            Some(buffers)
+                if buffers.vertex_buffer.size() >= vertex_bytes.len() as u64
+                    && buffers.index_buffer.size() >= index_bytes.len() as u64 =>
+            {
+                queue.write_buffer(&buffers.vertex_buffer, 0, vertex_bytes);
+                queue.write_buffer(&buffers.index_buffer, 0, index_bytes);
+                buffers.index_count = index_count;
+            }
+            _ => {
+                let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Vertex buffer"),
+                    contents: vertex_bytes,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+                let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("index buffer"),
+                    contents: index_bytes,
+                    usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                });
+                self.buffers = Some(ChunkBuffers {
+                    vertex_buffer,
+                    index_buffer,
+                    index_count,
+                });
+            }
+        }
+        (vertex_bytes.len() + index_bytes.len()) as u64
+    }
+
+    //None until the first non empty mesh got uploaded
+    pub fn buffers(&self) -> Option<&ChunkBuffers> {
+        self.buffers.as_ref()
+    }
 }
 
 fn generate_index_for_face(face_count: u32) -> [u32; 6] {
     let offset = face_count * 4;
-    let v1 = offset + 0;
+    let v1 = offset;
     let v2 = offset + 1;
     let v3 = offset + 3;
     let v4 = offset + 3;
     let v5 = offset + 2;
-    let v6 = offset + 0;
+    let v6 = offset;
 
     [v1, v2, v3, v4, v5, v6]
 }
 
-enum FaceType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaceType {
     Front,
     Back,
     Right,
@@ -149,47 +365,116 @@ enum FaceType {
     Bottom,
 }
 
-pub fn generate_voxel(
-    device: &wgpu::Device,
-    x: f32,
-    y: f32,
-    z: f32,
-) -> (wgpu::Buffer, wgpu::Buffer) {
-    let mut vertices = Vec::new();
-    let mut indiceses = Vec::new();
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshingMode {
+    //one quad per visible voxel face
+    Naive,
+    //coplanar faces of the same block get merged into bigger quads
+    Greedy,
+}
 
-    for i in 0..6 {
-        let mut face = FaceType::Front;
-        if i == 1{
-            face = FaceType::Back;
-        }
-        else if i == 2{
-            face = FaceType::Right;
-        }
-        else if i == 3{
-            face = FaceType::Left;
-        }
-        else if i == 4{
-            face = FaceType::Bottom;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshOptions {
+    pub mode: MeshingMode,
+    //when set every face gets slightly darkened by a hash of its world
+    //position, greedy quads take the shade of their first voxel
+    pub color_seed: Option<u64>,
+}
+
+//how much darker than the block color a face can get
+const SHADE_VARIATION: f32 = 0.12;
+
+//deterministic so re-meshing or another run produces the same colors
+fn face_shade(seed: u64, pos: WorldPos, face_type: FaceType) -> f32 {
+    let unit = crate::noise::position_hash(seed ^ face_type as u64, pos.x, pos.y, pos.z);
+    1.0 - SHADE_VARIATION * unit as f32
+}
+
+impl FaceType {
+    pub const ALL: [FaceType; 6] = [
+        FaceType::Front,
+        FaceType::Back,
+        FaceType::Right,
+        FaceType::Left,
+        FaceType::Top,
+        FaceType::Bottom,
+    ];
+
+    pub fn offset(self) -> (i32, i32, i32) {
+        match self {
+            FaceType::Front => (0, 0, 1),
+            FaceType::Back => (0, 0, -1),
+            FaceType::Right => (1, 0, 0),
+            FaceType::Left => (-1, 0, 0),
+            FaceType::Top => (0, 1, 0),
+            FaceType::Bottom => (0, -1, 0),
         }
-        else if i == 5{
-            face = FaceType::Top;
+    }
+
+    //0 for x, 1 for y and 2 for z
+    fn axis(self) -> usize {
+        match self {
+            FaceType::Right | FaceType::Left => 0,
+            FaceType::Top | FaceType::Bottom => 1,
+            FaceType::Front | FaceType::Back => 2,
         }
-        let vertex_face = generate_voxel_face(
-            x as f32,
-            y as f32,
-            z as f32,
-            &cgmath::Vector3::<usize>::new(0, 0, 0),
-            face,
-        );
+    }
+}
+
+//a single cube with its minimum corner at (x, y, z), 24 vertices and 36 indices
+pub fn voxel_mesh(x: f32, y: f32, z: f32, size: f32, block: &Block) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indiceses = Vec::new();
+
+    for (i, face) in FaceType::ALL.into_iter().enumerate() {
+        let vertex_face = generate_voxel_face(x, y, z, [size; 3], face, block, 1.0);
         for vertex in vertex_face.iter() {
             vertices.push(*vertex);
         }
-        let indices = generate_index_for_face(i);
+        let indices = generate_index_for_face(i as u32);
         for index in indices.iter() {
             indiceses.push(*index);
         }
     }
+    (vertices, indiceses)
+}
+
+//the 12 edges of a cube as a line list, corner i is offset by size on
+//the axes whose bit is set in i (x is bit 0, y bit 1, z bit 2)
+pub fn edge_mesh(x: f32, y: f32, z: f32, size: f32, color: [f32; 3]) -> (Vec<Vertex>, Vec<u32>) {
+    let size = size * BLOCK_SIZE;
+    let vertices = (0..8)
+        .map(|i| Vertex {
+            position: [
+                x + (i & 1) as f32 * size,
+                y + (i >> 1 & 1) as f32 * size,
+                z + (i >> 2 & 1) as f32 * size,
+            ],
+            color,
+            normal: [0.0, 0.0, 0.0],
+            emissive: 1.0,
+        })
+        .collect();
+    //every corner connects to the corners one axis further along
+    let mut indices = Vec::with_capacity(24);
+    for corner in 0..8u32 {
+        for axis in [1, 2, 4] {
+            if corner & axis == 0 {
+                indices.extend([corner, corner | axis]);
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+pub fn generate_voxel(
+    device: &wgpu::Device,
+    x: f32,
+    y: f32,
+    z: f32,
+    block: &Block,
+) -> (wgpu::Buffer, wgpu::Buffer) {
+    let (vertices, indiceses) = voxel_mesh(x, y, z, 1.0, block);
 
     let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Vertex buffer"),
@@ -208,96 +493,98 @@ fn generate_voxel_face(
     x: f32,
     y: f32,
     z: f32,
-    world_coordinates: &cgmath::Vector3<usize>,
+    size: [f32; 3],
     face_type: FaceType,
+    block: &Block,
+    shade: f32,
 ) -> [Vertex; 4] {
-    let x = x + world_coordinates.x as f32;
-    let z = z + world_coordinates.z as f32;
-
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
-    let color_index = rng.gen_range(0..6);
-    let mut color = [0.0, 0.0, 0.0];
-    if color_index == 0 {
-        color = FRONT_COLOR;
-    } else if color_index == 1 {
-        color = BACK_COLOR;
-    } else if color_index == 2 {
-        color = RIGHT_COLOR;
-    } else if color_index == 3 {
-        color = LEFT_COLOR;
-    } else if color_index == 4 {
-        color = BOTTOM_COLOR;
-    } else if color_index == 5 {
-        color = TOP_COLOR;
-    }
+
+    let (w, h, d) = (
+        size[0] * BLOCK_SIZE,
+        size[1] * BLOCK_SIZE,
+        size[2] * BLOCK_SIZE,
+    );
+    let color = block.face_color(face_type).map(|c| c * shade);
+    let emissive = if block.emissive { 1.0 } else { 0.0 };
 
     match face_type {
         FaceType::Front => {
             let v1 = Vertex {
-                position: [x, y, z + BLOCK_SIZE],
-                color: color,
+                position: [x, y, z + d],
+                color,
+                emissive,
                 normal: [0.0, 0.0, 1.0],
             };
             let v2 = Vertex {
-                position: [x + BLOCK_SIZE, y, z + BLOCK_SIZE],
-                color: color,
+                position: [x + w, y, z + d],
+                color,
+                emissive,
                 normal: [0.0, 0.0, 1.0],
             };
             let v3 = Vertex {
-                position: [x, y + BLOCK_SIZE, z + BLOCK_SIZE],
-                color: color,
+                position: [x, y + h, z + d],
+                color,
+                emissive,
                 normal: [0.0, 0.0, 1.0],
             };
             let v4 = Vertex {
-                position: [x + BLOCK_SIZE, y + BLOCK_SIZE, z + BLOCK_SIZE],
-                color: color,
+                position: [x + w, y + h, z + d],
+                color,
+                emissive,
                 normal: [0.0, 0.0, 1.0],
             };
             [v1, v2, v3, v4]
         }
         FaceType::Back => {
             let v1 = Vertex {
-                position: [x + BLOCK_SIZE, y, z],
-                color: color,
+                position: [x + w, y, z],
+                color,
+                emissive,
                 normal: [0.0, 0.0, -1.0],
             };
             let v2 = Vertex {
                 position: [x, y, z],
-                color: color,
+                color,
+                emissive,
                 normal: [0.0, 0.0, -1.0],
             };
             let v3 = Vertex {
-                position: [x + BLOCK_SIZE, y + BLOCK_SIZE, z],
-                color: color,
+                position: [x + w, y + h, z],
+                color,
+                emissive,
                 normal: [0.0, 0.0, -1.0],
             };
             let v4 = Vertex {
-                position: [x, y + BLOCK_SIZE, z],
-                color: color,
+                position: [x, y + h, z],
+                color,
+                emissive,
                 normal: [0.0, 0.0, -1.0],
             };
             [v1, v2, v3, v4]
         }
         FaceType::Right => {
             let v1 = Vertex {
-                position: [x + BLOCK_SIZE, y, z + BLOCK_SIZE],
-                color: color,
+                position: [x + w, y, z + d],
+                color,
+                emissive,
                 normal: [1.0, 0.0, 0.0],
             };
             let v2 = Vertex {
-                position: [x + BLOCK_SIZE, y, z],
-                color: color,
+                position: [x + w, y, z],
+                color,
+                emissive,
                 normal: [1.0, 0.0, 0.0],
             };
             let v3 = Vertex {
-                position: [x + BLOCK_SIZE, y + BLOCK_SIZE, z + BLOCK_SIZE],
-                color: color,
+                position: [x + w, y + h, z + d],
+                color,
+                emissive,
                 normal: [1.0, 0.0, 0.0],
             };
             let v4 = Vertex {
-                position: [x + BLOCK_SIZE, y + BLOCK_SIZE, z],
-                color: color,
+                position: [x + w, y + h, z],
+                color,
+                emissive,
                 normal: [1.0, 0.0, 0.0],
             };
             [v1, v2, v3, v4]
@@ -305,22 +592,26 @@ fn generate_voxel_face(
         FaceType::Left => {
             let v1 = Vertex {
                 position: [x, y, z],
-                color: color,
+                color,
+                emissive,
                 normal: [-1.0, 0.0, 0.0],
             };
             let v2 = Vertex {
-                position: [x, y, z + BLOCK_SIZE],
-                color: color,
+                position: [x, y, z + d],
+                color,
+                emissive,
                 normal: [-1.0, 0.0, 0.0],
             };
             let v3 = Vertex {
-                position: [x, y + BLOCK_SIZE, z],
-                color: color,
+                position: [x, y + h, z],
+                color,
+                emissive,
                 normal: [-1.0, 0.0, 0.0],
             };
             let v4 = Vertex {
-                position: [x, y + BLOCK_SIZE, z + BLOCK_SIZE],
-                color: color,
+                position: [x, y + h, z + d],
+                color,
+                emissive,
                 normal: [-1.0, 0.0, 0.0],
             };
             [v1, v2, v3, v4]
@@ -328,45 +619,53 @@ fn generate_voxel_face(
         FaceType::Bottom => {
             let v1 = Vertex {
                 position: [x, y, z],
-                color: color,
+                color,
+                emissive,
                 normal: [0.0, -1.0, 0.0],
             };
             let v2 = Vertex {
-                position: [x + BLOCK_SIZE, y, z],
-                color: color,
+                position: [x + w, y, z],
+                color,
+                emissive,
                 normal: [0.0, -1.0, 0.0],
             };
             let v3 = Vertex {
-                position: [x, y, z + BLOCK_SIZE],
-                color: color,
+                position: [x, y, z + d],
+                color,
+                emissive,
                 normal: [0.0, -1.0, 0.0],
             };
             let v4 = Vertex {
-                position: [x + BLOCK_SIZE, y, z + BLOCK_SIZE],
-                color: color,
+                position: [x + w, y, z + d],
+                color,
+                emissive,
                 normal: [0.0, -1.0, 0.0],
             };
             [v1, v2, v3, v4]
         }
         FaceType::Top => {
             let v1 = Vertex {
-                position: [x, y + BLOCK_SIZE, z + BLOCK_SIZE],
-                color: color,
+                position: [x, y + h, z + d],
+                color,
+                emissive,
                 normal: [0.0, 1.0, 0.0],
             };
             let v2 = Vertex {
-                position: [x + BLOCK_SIZE, y + BLOCK_SIZE, z + BLOCK_SIZE],
-                color: color,
+                position: [x + w, y + h, z + d],
+                color,
+                emissive,
                 normal: [0.0, 1.0, 0.0],
             };
             let v3 = Vertex {
-                position: [x, y + BLOCK_SIZE, z],
-                color: color,
+                position: [x, y + h, z],
+                color,
+                emissive,
                 normal: [0.0, 1.0, 0.0],
             };
             let v4 = Vertex {
-                position: [x + BLOCK_SIZE, y + BLOCK_SIZE, z],
-                color: color,
+                position: [x + w, y + h, z],
+                color,
+                emissive,
                 normal: [0.0, 1.0, 0.0],
             };
             [v1, v2, v3, v4]
@@ -387,6 +686,8 @@ pub struct Vertex {
     position: [f32; 3],
     color: [f32; 3],
     normal: [f32; 3],
+    //1.0 for blocks that aren't affected by lighting
+    emissive: f32,
 }
 
 impl Vertex {
@@ -416,7 +717,197 @@ impl Vertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute{
+                    offset: mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32,
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::position_hash;
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::builtin()
+    }
+
+    fn options(mode: MeshingMode, color_seed: Option<u64>) -> MeshOptions {
+        MeshOptions { mode, color_seed }
+    }
+
+    const SIZE: ChunkSize = ChunkSize::DEFAULT;
+
+    fn staircase(block: BlockId) -> Vec<BlockId> {
+        let mut chunk_data = vec![AIR; SIZE.volume()];
+        for y in 0..SIZE.height {
+            for z in y..SIZE.depth {
+                for x in 0..SIZE.width {
+                    chunk_data[LocalPos::new(x, y, z).index(SIZE)] = block;
+                }
+            }
+        }
+        chunk_data
+    }
+
+    //surface area covered by the quads of a mesh, per face normal
+    fn covered_area(chunk: &ChunkMeshData) -> Vec<([i32; 3], f32)> {
+        use cgmath::InnerSpace;
+        let mut areas: Vec<([i32; 3], f32)> = Vec::new();
+        for quad in chunk.vertices.chunks(4) {
+            let corner = cgmath::Vector3::from(quad[0].position);
+            let a = cgmath::Vector3::from(quad[1].position) - corner;
+            let b = cgmath::Vector3::from(quad[2].position) - corner;
+            let area = a.cross(b).magnitude();
+            let normal = quad[0].normal.map(|n| n as i32);
+            match areas.iter_mut().find(|(n, _)| *n == normal) {
+                Some((_, total)) => *total += area,
+                None => areas.push((normal, area)),
+            }
+        }
+        areas.sort_by_key(|(normal, _)| *normal);
+        areas
+    }
+
+    fn mesh_both(chunk_data: Vec<BlockId>, size: ChunkSize) -> (ChunkMeshData, ChunkMeshData) {
+        let registry = registry();
+        let mut naive = ChunkMeshData::new(ChunkPos::new(0, 0, 0), size);
+        naive.chunk_data = PaletteStorage::from_slice(&chunk_data);
+        let mut greedy = naive.snapshot();
+        naive.generate_mesh(&registry, options(MeshingMode::Naive, None), |_, _, _| AIR);
+        greedy.generate_mesh(&registry, options(MeshingMode::Greedy, None), |_, _, _| AIR);
+        (naive, greedy)
+    }
+
+    fn assert_same_area(
+        chunk_data: Vec<BlockId>,
+        size: ChunkSize,
+    ) -> (ChunkMeshData, ChunkMeshData) {
+        let (naive, greedy) = mesh_both(chunk_data, size);
+        let (naive_area, greedy_area) = (covered_area(&naive), covered_area(&greedy));
+        assert_eq!(naive_area.len(), greedy_area.len());
+        for ((naive_normal, naive_area), (greedy_normal, greedy_area)) in
+            naive_area.iter().zip(greedy_area.iter())
+        {
+            assert_eq!(naive_normal, greedy_normal);
+            assert!((naive_area - greedy_area).abs() < 1e-3);
+        }
+        assert!(greedy.num_of_faces <= naive.num_of_faces);
+        (naive, greedy)
+    }
+
+    #[test]
+    fn greedy_full_chunk_is_six_quads() {
+        let stone = registry().id("stone").unwrap();
+        let (naive, greedy) = assert_same_area(vec![stone; SIZE.volume()], SIZE);
+        assert_eq!(naive.num_of_faces as usize, 6 * SIZE.width * SIZE.height);
+        assert_eq!(greedy.num_of_faces, 6);
+    }
+
+    #[test]
+    fn other_chunk_sizes_mesh_completely() {
+        let stone = registry().id("stone").unwrap();
+        for size in [
+            ChunkSize::new(32, 32, 32),
+            ChunkSize::new(16, 256, 16),
+            ChunkSize::new(5, 3, 9),
+        ] {
+            let (naive, greedy) = assert_same_area(vec![stone; size.volume()], size);
+            let (w, h, d) = (size.width, size.height, size.depth);
+            assert_eq!(naive.num_of_faces as usize, 2 * (w * h + w * d + h * d));
+            assert_eq!(greedy.num_of_faces, 6);
+        }
+    }
+
+    #[test]
+    fn meshes_start_at_the_chunk_origin() {
+        let stone = registry().id("stone").unwrap();
+        let mut chunk = ChunkMeshData::new(ChunkPos::new(1, -2, -1), SIZE);
+        chunk.chunk_data = PaletteStorage::new(SIZE.volume(), stone);
+        chunk.generate_mesh(&registry(), options(MeshingMode::Greedy, None), |_, _, _| AIR);
+        for vertex in &chunk.vertices {
+            let [x, y, z] = vertex.position;
+            assert!((16.0..=32.0).contains(&x), "{:?}", vertex.position);
+            assert!((-32.0..=-16.0).contains(&y), "{:?}", vertex.position);
+            assert!((-16.0..=0.0).contains(&z), "{:?}", vertex.position);
+        }
+    }
+
+    #[test]
+    fn greedy_matches_naive_on_staircase() {
+        assert_same_area(staircase(registry().id("grass").unwrap()), SIZE);
+    }
+
+    #[test]
+    fn greedy_matches_naive_on_mixed_blocks() {
+        let registry = registry();
+        let blocks = [
+            AIR,
+            registry.id("stone").unwrap(),
+            registry.id("dirt").unwrap(),
+            registry.id("glass").unwrap(),
+        ];
+        let chunk_data = (0..SIZE.volume())
+            .map(|i| blocks[(position_hash(12345, i as i32, 0, 0) * blocks.len() as f64) as usize])
+            .collect();
+        assert_same_area(chunk_data, SIZE);
+    }
+
+    #[test]
+    fn edge_mesh_is_the_twelve_edges() {
+        let (vertices, indices) = edge_mesh(1.0, 2.0, 3.0, 1.0, [1.0; 3]);
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(indices.len(), 24);
+        let mut edges = Vec::new();
+        for edge in indices.chunks(2) {
+            let [a, b] = [edge[0], edge[1]].map(|i| vertices[i as usize].position);
+            //no face diagonals, every edge runs along one axis
+            let differs = (0..3).filter(|&axis| a[axis] != b[axis]).count();
+            assert_eq!(differs, 1, "{:?} to {:?}", a, b);
+            edges.push((edge[0].min(edge[1]), edge[0].max(edge[1])));
+        }
+        edges.sort();
+        edges.dedup();
+        assert_eq!(edges.len(), 12);
+    }
+
+    #[test]
+    fn greedy_keeps_different_blocks_apart() {
+        let registry = registry();
+        let (stone, dirt) = (registry.id("stone").unwrap(), registry.id("dirt").unwrap());
+        let mut chunk_data = vec![AIR; SIZE.volume()];
+        for x in 0..SIZE.width {
+            let block = if x < SIZE.width / 2 { stone } else { dirt };
+            chunk_data[LocalPos::new(x, 0, 0).index(SIZE)] = block;
+        }
+        let (_, greedy) = assert_same_area(chunk_data, SIZE);
+        //top, bottom, front and back split in two, plus both ends
+        assert_eq!(greedy.num_of_faces, 10);
+    }
+
+    fn colors(chunk_data: &[BlockId], color_seed: Option<u64>) -> Vec<[f32; 3]> {
+        let mut chunk = ChunkMeshData::new(ChunkPos::new(1, 0, -2), SIZE);
+        chunk.chunk_data = PaletteStorage::from_slice(chunk_data);
+        chunk.generate_mesh(&registry(), options(MeshingMode::Naive, color_seed), |_, _, _| AIR);
+        chunk.vertices.iter().map(|v| v.color).collect()
+    }
+
+    #[test]
+    fn face_colors_are_reproducible() {
+        let chunk_data = staircase(registry().id("grass").unwrap());
+
+        assert_eq!(colors(&chunk_data, Some(7)), colors(&chunk_data, Some(7)));
+        assert_ne!(colors(&chunk_data, Some(7)), colors(&chunk_data, Some(8)));
+
+        //without a seed faces use the plain block colors
+        let grass = registry().get(registry().id("grass").unwrap()).clone();
+        let plain = colors(&chunk_data, None);
+        assert!(plain
+            .iter()
+            .all(|c| *c == grass.face_color(FaceType::Top) || *c == grass.face_color(FaceType::Front)));
+    }
+}
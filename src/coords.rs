@@ -0,0 +1,243 @@
+use std::ops::Add;
+
+use cgmath::{Point3, Vector3};
+
+//dimensions of a chunk in blocks, a setting of the world
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkSize {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+}
+
+impl ChunkSize {
+    pub const DEFAULT: ChunkSize = ChunkSize::new(16, 16, 16);
+
+    pub const fn new(width: usize, height: usize, depth: usize) -> Self {
+        Self {
+            width,
+            height,
+            depth,
+        }
+    }
+
+    pub const fn volume(self) -> usize {
+        self.width * self.height * self.depth
+    }
+
+    //for local coordinates that can point outside of the chunk
+    pub fn contains(self, x: i32, y: i32, z: i32) -> bool {
+        (0..self.width as i32).contains(&x)
+            && (0..self.height as i32).contains(&y)
+            && (0..self.depth as i32).contains(&z)
+    }
+}
+
+//a block anywhere in the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WorldPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+//a chunk, chunk (1, 0, 0) starts at world x = width
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+//a block inside of a chunk, always smaller than the chunk size
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LocalPos {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+impl WorldPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    //the block the point is in
+    pub fn from_point(point: Point3<f32>) -> Self {
+        Self::new(
+            point.x.floor() as i32,
+            point.y.floor() as i32,
+            point.z.floor() as i32,
+        )
+    }
+
+    //rounds towards negative infinity, block -1 is in chunk -1
+    pub fn to_chunk(self, size: ChunkSize) -> (ChunkPos, LocalPos) {
+        let (width, height, depth) = (size.width as i32, size.height as i32, size.depth as i32);
+        let chunk = ChunkPos::new(
+            self.x.div_euclid(width),
+            self.y.div_euclid(height),
+            self.z.div_euclid(depth),
+        );
+        let local = LocalPos::new(
+            self.x.rem_euclid(width) as usize,
+            self.y.rem_euclid(height) as usize,
+            self.z.rem_euclid(depth) as usize,
+        );
+        (chunk, local)
+    }
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    //world position of the chunk's (0, 0, 0) block
+    pub fn origin(self, size: ChunkSize) -> WorldPos {
+        WorldPos::new(
+            self.x * size.width as i32,
+            self.y * size.height as i32,
+            self.z * size.depth as i32,
+        )
+    }
+
+    pub fn world_pos(self, local: LocalPos, size: ChunkSize) -> WorldPos {
+        self.origin(size) + Vector3::new(local.x as i32, local.y as i32, local.z as i32)
+    }
+}
+
+impl LocalPos {
+    pub const fn new(x: usize, y: usize, z: usize) -> Self {
+        Self { x, y, z }
+    }
+
+    //y changes fastest so a column of blocks is contiguous, terrain is
+    //mostly vertical runs and compresses well in that order
+    pub fn index(self, size: ChunkSize) -> usize {
+        debug_assert!(self.x < size.width && self.y < size.height && self.z < size.depth);
+        self.y + size.height * (self.x + size.width * self.z)
+    }
+
+    pub fn from_index(index: usize, size: ChunkSize) -> Self {
+        debug_assert!(index < size.volume());
+        let column = index / size.height;
+        Self::new(
+            column % size.width,
+            index % size.height,
+            column / size.width,
+        )
+    }
+}
+
+impl Add<Vector3<i32>> for WorldPos {
+    type Output = WorldPos;
+
+    fn add(self, offset: Vector3<i32>) -> WorldPos {
+        WorldPos::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+impl Add<Vector3<i32>> for ChunkPos {
+    type Output = ChunkPos;
+
+    fn add(self, offset: Vector3<i32>) -> ChunkPos {
+        ChunkPos::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+impl From<WorldPos> for Vector3<i32> {
+    fn from(pos: WorldPos) -> Self {
+        Vector3::new(pos.x, pos.y, pos.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::position_hash;
+
+    const SIZES: [ChunkSize; 5] = [
+        ChunkSize::DEFAULT,
+        ChunkSize::new(16, 256, 16),
+        ChunkSize::new(3, 5, 7),
+        ChunkSize::new(32, 1, 2),
+        ChunkSize::new(1, 9, 1),
+    ];
+
+    //in -range..=range on every axis
+    fn random_positions(count: usize, range: i32) -> Vec<WorldPos> {
+        let coord = |i: usize, axis: i32| {
+            let t = position_hash(0x1234_5678, i as i32, axis, 0);
+            (t * (2 * range + 1) as f64) as i32 - range
+        };
+        (0..count)
+            .map(|i| WorldPos::new(coord(i, 0), coord(i, 1), coord(i, 2)))
+            .collect()
+    }
+
+    #[test]
+    fn index_is_a_bijection() {
+        for size in SIZES {
+            let mut seen = vec![false; size.volume()];
+            for z in 0..size.depth {
+                for y in 0..size.height {
+                    for x in 0..size.width {
+                        let local = LocalPos::new(x, y, z);
+                        let index = local.index(size);
+                        assert!(index < size.volume(), "{:?} in {:?}", local, size);
+                        assert!(!seen[index], "{:?} in {:?} collides", local, size);
+                        seen[index] = true;
+                        assert_eq!(LocalPos::from_index(index, size), local);
+                    }
+                }
+            }
+            assert!(seen.iter().all(|s| *s));
+        }
+    }
+
+    #[test]
+    fn world_positions_round_trip() {
+        for size in SIZES {
+            for pos in random_positions(2000, 100_000) {
+                let (chunk, local) = pos.to_chunk(size);
+                assert!(local.x < size.width && local.y < size.height && local.z < size.depth);
+                assert_eq!(chunk.world_pos(local, size), pos, "{:?}", size);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_positions_round_down() {
+        let size = ChunkSize::new(16, 32, 16);
+        let (chunk, local) = WorldPos::new(-1, -1, -16).to_chunk(size);
+        assert_eq!(chunk, ChunkPos::new(-1, -1, -1));
+        assert_eq!(local, LocalPos::new(15, 31, 0));
+        assert_eq!(
+            ChunkPos::new(-2, 0, 3).origin(size),
+            WorldPos::new(-32, 0, 48)
+        );
+        assert_eq!(
+            WorldPos::from_point(Point3::new(-0.5, 2.9, -16.01)),
+            WorldPos::new(-1, 2, -17)
+        );
+    }
+
+    #[test]
+    fn neighbouring_blocks_stay_neighbours() {
+        //crossing a chunk border changes the chunk by one and wraps the local position
+        for size in SIZES {
+            for pos in random_positions(500, 1000) {
+                let (chunk, local) = pos.to_chunk(size);
+                let (next_chunk, next_local) = (pos + Vector3::new(1, 0, 0)).to_chunk(size);
+                if local.x + 1 == size.width {
+                    assert_eq!(next_chunk, chunk + Vector3::new(1, 0, 0));
+                    assert_eq!(next_local.x, 0);
+                } else {
+                    assert_eq!(next_chunk, chunk);
+                    assert_eq!(next_local.x, local.x + 1);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 pub struct DepthTexture {
+    #[allow(dead_code)]
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    #[allow(dead_code)]
     pub sampler: wgpu::Sampler,
 }
 
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+use crate::block::BlockId;
+use crate::coords::WorldPos;
+
+//a single block that was changed, enough to apply the change in both directions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockChange {
+    pub pos: WorldPos,
+    pub before: BlockId,
+    pub after: BlockId,
+}
+
+//undo and redo stacks of block edits. the world applies the changes,
+//this only keeps track of them
+pub struct EditHistory {
+    undo: VecDeque<BlockChange>,
+    redo: Vec<BlockChange>,
+    //oldest edits get dropped once there are more than this
+    limit: usize,
+}
+
+impl EditHistory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    //a new edit makes the undone ones unreachable
+    pub fn push(&mut self, change: BlockChange) {
+        if change.before == change.after || self.limit == 0 {
+            return;
+        }
+        self.redo.clear();
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(change);
+    }
+
+    //the change to revert, it's moved to the redo stack
+    pub fn undo(&mut self) -> Option<BlockChange> {
+        let change = self.undo.pop_back()?;
+        self.redo.push(change);
+        Some(change)
+    }
+
+    //the change to apply again
+    pub fn redo(&mut self) -> Option<BlockChange> {
+        let change = self.redo.pop()?;
+        self.undo.push_back(change);
+        Some(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(x: i32, before: BlockId, after: BlockId) -> BlockChange {
+        BlockChange {
+            pos: WorldPos::new(x, 0, 0),
+            before,
+            after,
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut history = EditHistory::new(10);
+        history.push(change(0, 0, 1));
+        history.push(change(1, 0, 2));
+
+        assert_eq!(history.undo(), Some(change(1, 0, 2)));
+        assert_eq!(history.undo(), Some(change(0, 0, 1)));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), Some(change(0, 0, 1)));
+
+        //a new edit after undoing throws away what could be redone
+        history.push(change(2, 3, 0));
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(change(2, 3, 0)));
+        assert_eq!(history.undo(), Some(change(0, 0, 1)));
+    }
+
+    #[test]
+    fn history_is_limited() {
+        let mut history = EditHistory::new(3);
+        for x in 0..5 {
+            history.push(change(x, 0, 1));
+        }
+        //writing the block that is already there isn't an edit
+        history.push(change(9, 1, 1));
+        let undone: Vec<i32> = std::iter::from_fn(|| history.undo())
+            .map(|c| c.pos.x)
+            .collect();
+        assert_eq!(undone, [4, 3, 2]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+//handed out with every job, setting it makes the workers skip the job
+//if they haven't started it yet
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "wasm32")] {
+        //no threads on the web, jobs run right away and wait for the next poll
+        pub struct JobPool<T> {
+            finished: std::cell::RefCell<Vec<T>>,
+        }
+
+        impl<T: Send + 'static> JobPool<T> {
+            pub fn new() -> Self {
+                Self {
+                    finished: std::cell::RefCell::new(Vec::new()),
+                }
+            }
+
+            pub fn spawn(&self, token: CancelToken, job: impl FnOnce() -> T + Send + 'static) {
+                if !token.is_cancelled() {
+                    self.finished.borrow_mut().push(job());
+                }
+            }
+
+            pub fn poll(&self) -> Vec<T> {
+                std::mem::take(&mut *self.finished.borrow_mut())
+            }
+        }
+    } else {
+        use std::sync::mpsc;
+        use std::sync::Mutex;
+
+        type Job<T> = Box<dyn FnOnce() -> T + Send>;
+
+        //a fixed set of worker threads, results are collected with poll
+        //on the thread that owns the pool
+        pub struct JobPool<T> {
+            jobs: mpsc::Sender<(CancelToken, Job<T>)>,
+            results: mpsc::Receiver<T>,
+        }
+
+        impl<T: Send + 'static> JobPool<T> {
+            pub fn new() -> Self {
+                //leaving one core for the render thread
+                let worker_count = std::thread::available_parallelism()
+                    .map(|n| n.get().saturating_sub(1).max(1))
+                    .unwrap_or(1);
+                Self::with_workers(worker_count)
+            }
+
+            fn with_workers(worker_count: usize) -> Self {
+                let (jobs, job_receiver) = mpsc::channel::<(CancelToken, Job<T>)>();
+                let (result_sender, results) = mpsc::channel();
+                let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+                for i in 0..worker_count {
+                    let job_receiver = job_receiver.clone();
+                    let result_sender = result_sender.clone();
+                    std::thread::Builder::new()
+                        .name(format!("chunk worker {}", i))
+                        .spawn(move || loop {
+                            //the lock is released before running the job
+                            let next = job_receiver.lock().unwrap().recv();
+                            //the pool got dropped
+                            let Ok((token, job)) = next else {
+                                break;
+                            };
+                            if token.is_cancelled() {
+                                continue;
+                            }
+                            if result_sender.send(job()).is_err() {
+                                break;
+                            }
+                        })
+                        .expect("couldn't spawn a chunk worker thread");
+                }
+
+                Self { jobs, results }
+            }
+
+            pub fn spawn(&self, token: CancelToken, job: impl FnOnce() -> T + Send + 'static) {
+                //only fails when every worker panicked
+                self.jobs
+                    .send((token, Box::new(job)))
+                    .expect("all chunk workers are dead");
+            }
+
+            pub fn poll(&self) -> Vec<T> {
+                self.results.try_iter().collect()
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    //polls until `count` results came back from the workers
+    fn wait_for<T: Send + 'static>(pool: &JobPool<T>, count: usize) -> Vec<T> {
+        let start = Instant::now();
+        let mut results = Vec::new();
+        while results.len() < count {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "jobs didn't finish"
+            );
+            results.extend(pool.poll());
+            std::thread::yield_now();
+        }
+        results
+    }
+
+    #[test]
+    fn poll_hands_back_every_result() {
+        let pool = JobPool::new();
+        assert!(pool.poll().is_empty());
+        for i in 0..20 {
+            pool.spawn(CancelToken::default(), move || i);
+        }
+        let mut results = wait_for(&pool, 20);
+        results.sort();
+        assert_eq!(results, (0..20).collect::<Vec<_>>());
+        assert!(pool.poll().is_empty());
+    }
+
+    #[test]
+    fn cancelled_jobs_are_skipped() {
+        //a single worker stuck on the first job, so the rest are still
+        //queued when they get cancelled
+        let pool = JobPool::with_workers(1);
+        let (unblock, blocked) = mpsc::channel::<()>();
+        pool.spawn(CancelToken::default(), move || {
+            blocked.recv().unwrap();
+            0
+        });
+        let tokens: Vec<CancelToken> = (1..=4)
+            .map(|i| {
+                let token = CancelToken::default();
+                pool.spawn(token.clone(), move || i);
+                token
+            })
+            .collect();
+        tokens[0].cancel();
+        tokens[2].cancel();
+
+        unblock.send(()).unwrap();
+        //jobs run in order, so 1 and 3 would have come back before 4
+        assert_eq!(wait_for(&pool, 3), vec![0, 2, 4]);
+    }
+}
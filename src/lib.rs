@@ -4,10 +4,28 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod biome;
+mod block;
 mod camera;
+mod camera_mode;
+mod cave;
 mod chunk;
+mod coords;
 mod depth_texture;
+mod edit;
+mod jobs;
+mod noise;
+mod orientation;
+mod palette;
+mod player;
+mod raycast;
+mod region;
 mod render;
+mod serialize;
+mod structure;
+mod terrain;
+mod timestep;
+mod world;
 mod egui_integration;
 
 #[cfg(target_arch = "wasm32")]
@@ -15,9 +33,18 @@ use wasm_bindgen::prelude::*;
 
 struct State {
     camera: camera::Camera,
+    //the camera of the tick before, rendering blends the two
+    previous_camera: camera::Camera,
+    timestep: timestep::FixedTimestep,
     camera_controller: camera::CameraController,
     projection: camera::Projection,
     render: render::Render,
+    player: player::Player,
+    camera_modes: Vec<Box<dyn camera_mode::CameraMode>>,
+    //index into camera_modes
+    camera_mode: usize,
+    //mouse look only works while the cursor is grabbed
+    cursor_grabbed: bool,
 }
 
 impl State {
@@ -25,11 +52,9 @@ impl State {
         //positive Z points away from the screen
         let camera = camera::Camera {
             camera_pos: (0.0, 15.0, 1.0).into(),
-            camera_front: (0.0, 0.0, -1.0).into(),
+            orientation: orientation::Orientation::new(cgmath::Deg(45.0), cgmath::Deg(0.0)),
             speed: 5.0,
-            angular_speed: 2.0,
-            yaw: 45.0,
-            pitch: 0.0,
+            angular_speed: cgmath::Rad(2.0),
         };
         let render = render::Render::new(window).await;
 
@@ -37,14 +62,46 @@ impl State {
         let projection =
             camera::Projection::new(render.width() / render.height(), 45.0, 0.1, 100.0);
 
+        let player = player::Player::new(
+            camera.camera_pos - cgmath::Vector3::unit_y() * player::EYE_HEIGHT,
+        );
         Self {
             camera,
+            previous_camera: camera,
+            timestep: timestep::FixedTimestep::new(
+                timestep::TICK,
+                timestep::MAX_TICKS_PER_FRAME,
+            ),
             camera_controller,
             projection,
             render,
+            player,
+            camera_modes: camera_mode::all(),
+            camera_mode: 0,
+            cursor_grabbed: false,
         }
     }
 
+    //hides the cursor and keeps it in the window, not every platform
+    //supports both grab modes
+    fn set_cursor_grab(&mut self, grab: bool) {
+        use winit::window::CursorGrabMode;
+        let window = self.render.window();
+        let result = if grab {
+            window
+                .set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = result {
+            log::warn!("couldn't grab the cursor: {}", e);
+            return;
+        }
+        window.set_cursor_visible(!grab);
+        self.cursor_grabbed = grab;
+    }
+
     pub fn window(&self) -> &Window {
         self.render.window()
     }
@@ -56,14 +113,67 @@ impl State {
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::G),
+                        ..
+                    },
+                ..
+            } => {
+                self.set_cursor_grab(!self.cursor_grabbed);
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::C),
+                        ..
+                    },
+                ..
+            } => {
+                self.camera_mode = (self.camera_mode + 1) % self.camera_modes.len();
+                log::info!("camera mode: {}", self.camera_modes[self.camera_mode].name());
+                return true;
+            }
+            WindowEvent::Focused(false) if self.cursor_grabbed => self.set_cursor_grab(false),
+            //the click that focuses the window shouldn't break or place a block
+            WindowEvent::MouseInput { .. } if !self.cursor_grabbed => return false,
+            _ => {}
+        }
         self.camera_controller.process_events(event);
         self.render.process_events(event);
         false
     }
 
-    fn update(&mut self, dt: instant::Duration) {
-        self.camera.update_camera(&self.camera_controller, dt);
-        self.render.update(&self.camera, &self.projection);
+    //runs the ticks the frame time adds up to, then draws the camera
+    //somewhere between the last two ticks so movement stays smooth when
+    //the frame rate doesn't match the tick rate
+    fn update(&mut self, frame_time: instant::Duration) {
+        for _ in 0..self.timestep.advance(frame_time) {
+            self.previous_camera = self.camera;
+            self.tick();
+        }
+        let camera = self
+            .previous_camera
+            .interpolate(&self.camera, self.timestep.alpha());
+        self.render.update(&camera, &self.player, &self.projection);
+    }
+
+    //a single step of the simulation, always the same length
+    fn tick(&mut self) {
+        let dt = self.timestep.tick();
+        self.camera.look(&mut self.camera_controller, dt);
+        self.camera_modes[self.camera_mode].update(
+            &mut self.camera,
+            &mut self.player,
+            &self.camera_controller,
+            self.render.world(),
+            dt.as_secs_f32(),
+        );
     }
 }
 
@@ -109,38 +219,48 @@ pub async fn run() {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window().id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
+            } if window_id == state.window().id() && !state.input(event) => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
                             ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &mut so w have to dereference it twice
-                            state.resize(**new_inner_size);
-                        }
-                        _ => {}
-                    }
+                        },
+                    ..
+                } => {
+                    state.render.save();
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    // new_inner_size is &mut so w have to dereference it twice
+                    state.resize(**new_inner_size);
                 }
+                _ => {}
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if state.cursor_grabbed => {
+                state.camera_controller.process_mouse(delta.0, delta.1);
             }
             Event::RedrawRequested(window_id) if window_id == state.window().id() => {
                 let now = instant::Instant::now();
-                let dt = now - last_render_time;
+                let frame_time = now - last_render_time;
                 last_render_time = now;
-                state.update(dt);
+                state.update(frame_time);
                 
                 match state.render.render() {
-                    Ok(_) => {}
+                    Ok(_) => {
+                        let stats = state.render.frame_stats();
+                        if stats.chunks_uploaded > 0 {
+                            log::debug!("{}", stats);
+                        }
+                    }
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         state.render.resize(state.render.get_size());
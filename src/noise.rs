@@ -0,0 +1,161 @@
+//seeded gradient (improved Perlin) noise, the same seed always
+//produces the same values so chunks line up no matter when they're generated
+#[derive(Clone)]
+pub struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        //fisher-yates driven by splitmix64
+        let mut state = seed;
+        for i in (1..table.len()).rev() {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            table.swap(i, (mix(state) % (i as u64 + 1)) as usize);
+        }
+
+        let mut permutation = [0; 512];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = table[i & 255];
+        }
+        Self { permutation }
+    }
+
+    //roughly in -1..1
+    pub fn get(&self, x: f64, y: f64, z: f64) -> f64 {
+        let p = &self.permutation;
+        let (xi, yi, zi) = (
+            x.floor() as i64 as usize & 255,
+            y.floor() as i64 as usize & 255,
+            z.floor() as i64 as usize & 255,
+        );
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    //a 2d slice of the 3d noise, used for heightmaps
+    pub fn get_2d(&self, x: f64, z: f64) -> f64 {
+        self.get(x, 0.5, z)
+    }
+
+    //octaves of noise, each one with double the frequency and half the
+    //amplitude of the previous one, normalized back into -1..1
+    pub fn fbm_2d(&self, x: f64, z: f64, octaves: u32) -> f64 {
+        let mut total = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut max = 0.0;
+        for octave in 0..octaves {
+            //offset every octave so they don't all share the lattice origin
+            let offset = octave as f64 * 17.31;
+            total += self.get_2d(x * frequency + offset, z * frequency + offset) * amplitude;
+            max += amplitude;
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+        total / max
+    }
+}
+
+//splitmix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+//white noise for a single block, in 0..1
+pub fn position_hash(seed: u64, x: i32, y: i32, z: i32) -> f64 {
+    let h = mix(seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u32 as u64).wrapping_mul(0x1656_67B1_9E37_79F9));
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_noise() {
+        let (a, b, other) = (Perlin::new(1), Perlin::new(1), Perlin::new(2));
+        let points = [(0.3, 0.7), (-120.25, 33.5), (1.0e5, -7.75)];
+        for (x, z) in points {
+            assert_eq!(a.fbm_2d(x, z, 4), b.fbm_2d(x, z, 4));
+            assert!((-1.0..=1.0).contains(&a.fbm_2d(x, z, 4)));
+        }
+        assert!(points
+            .iter()
+            .any(|&(x, z)| a.fbm_2d(x, z, 4) != other.fbm_2d(x, z, 4)));
+        assert_eq!(position_hash(9, -3, 4, 5), position_hash(9, -3, 4, 5));
+        assert_ne!(position_hash(9, -3, 4, 5), position_hash(10, -3, 4, 5));
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let noise = Perlin::new(5);
+        //across lattice cells, including negative ones
+        let mut x = -3.0;
+        while x < 3.0 {
+            let step = (noise.get_2d(x + 0.001, 0.4) - noise.get_2d(x, 0.4)).abs();
+            assert!(step < 0.01, "{}: {}", x, step);
+            x += 0.01;
+        }
+    }
+}
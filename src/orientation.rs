@@ -0,0 +1,139 @@
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+
+//looking straight up or down flips the view, pitch stops just before that
+pub const MAX_PITCH: Deg<f32> = Deg(89.0);
+
+//which way something looks. yaw turns around the y axis and is 0 along +x,
+//90 degrees along +z. pitch is positive when looking up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orientation {
+    //always in -180..180 degrees
+    yaw: Rad<f32>,
+    //always in -MAX_PITCH..=MAX_PITCH
+    pitch: Rad<f32>,
+}
+
+impl Orientation {
+    pub fn new(yaw: impl Into<Rad<f32>>, pitch: impl Into<Rad<f32>>) -> Self {
+        let mut orientation = Self {
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+        };
+        orientation.set(yaw.into(), pitch.into());
+        orientation
+    }
+
+    pub fn rotate(&mut self, yaw: impl Into<Rad<f32>>, pitch: impl Into<Rad<f32>>) {
+        self.set(self.yaw + yaw.into(), self.pitch + pitch.into());
+    }
+
+    fn set(&mut self, yaw: Rad<f32>, pitch: Rad<f32>) {
+        self.yaw = wrap(yaw);
+        let max: Rad<f32> = MAX_PITCH.into();
+        self.pitch = Rad(pitch.0.clamp(-max.0, max.0));
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        let (yaw, pitch) = (self.yaw.0, self.pitch.0);
+        Vector3::new(
+            yaw.cos() * pitch.cos(),
+            pitch.sin(),
+            yaw.sin() * pitch.cos(),
+        )
+        .normalize()
+    }
+
+    //along the ground, pitch doesn't tilt it
+    pub fn right(&self) -> Vector3<f32> {
+        Vector3::new(-self.yaw.0.sin(), 0.0, self.yaw.0.cos())
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.right().cross(self.forward())
+    }
+
+    //t = 0 is self and t = 1 is other, yaw turns the short way around
+    pub fn lerp(&self, other: &Orientation, t: f32) -> Orientation {
+        let yaw = wrap(other.yaw - self.yaw);
+        Orientation::new(
+            self.yaw + yaw * t,
+            self.pitch + (other.pitch - self.pitch) * t,
+        )
+    }
+}
+
+//into -pi..pi
+fn wrap(angle: Rad<f32>) -> Rad<f32> {
+    use std::f32::consts::{PI, TAU};
+    let wrapped = (angle.0 + PI).rem_euclid(TAU) - PI;
+    Rad(wrapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn basis_points_the_right_way() {
+        let orientation = Orientation::new(Deg(0.0), Deg(0.0));
+        assert_close(orientation.forward(), Vector3::unit_x());
+        assert_close(orientation.right(), Vector3::unit_z());
+        assert_close(orientation.up(), Vector3::unit_y());
+
+        let orientation = Orientation::new(Deg(-90.0), Deg(0.0));
+        assert_close(orientation.forward(), -Vector3::unit_z());
+        assert_close(orientation.right(), Vector3::unit_x());
+
+        let orientation = Orientation::new(Deg(90.0), Deg(45.0));
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(orientation.forward(), Vector3::new(0.0, half, half));
+        assert_close(orientation.right(), -Vector3::unit_x());
+        assert_close(orientation.up(), Vector3::new(0.0, half, -half));
+    }
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for yaw in (-360..=360).step_by(15) {
+            for pitch in (-89..=89).step_by(7) {
+                let orientation = Orientation::new(Deg(yaw as f32), Deg(pitch as f32));
+                let (f, r, u) = (orientation.forward(), orientation.right(), orientation.up());
+                for v in [f, r, u] {
+                    assert!((v.magnitude() - 1.0).abs() < 1e-5);
+                }
+                assert!(f.dot(r).abs() < 1e-5 && f.dot(u).abs() < 1e-5 && r.dot(u).abs() < 1e-5);
+                //right handed, like the view matrix expects
+                assert_close(f.cross(u), r);
+                assert!(u.y >= 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn angles_are_clamped_and_wrapped() {
+        let mut orientation = Orientation::new(Deg(45.0), Deg(120.0));
+        assert_eq!(orientation.pitch, MAX_PITCH.into());
+        orientation.rotate(Deg(0.0), Deg(-500.0));
+        assert_eq!(orientation.pitch, (-MAX_PITCH).into());
+
+        orientation.rotate(Deg(360.0 * 3.0), Deg(0.0));
+        assert!((orientation.yaw.0 - Rad::from(Deg(45.0)).0).abs() < 1e-4);
+        let orientation = Orientation::new(Deg(190.0), Deg(0.0));
+        assert!((Deg::from(orientation.yaw).0 + 170.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lerp_takes_the_short_way() {
+        let a = Orientation::new(Deg(170.0), Deg(-20.0));
+        let b = Orientation::new(Deg(-170.0), Deg(40.0));
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_close(a.lerp(&b, 1.0).forward(), b.forward());
+
+        let half = a.lerp(&b, 0.5);
+        assert!((Deg::from(half.yaw).0.abs() - 180.0).abs() < 1e-3);
+        assert!((Deg::from(half.pitch).0 - 10.0).abs() < 1e-3);
+    }
+}
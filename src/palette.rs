@@ -0,0 +1,247 @@
+use crate::block::BlockId;
+
+//block ids stored as indices into a small per chunk palette, packed with
+//as few bits as the palette needs. a chunk that is a single block type
+//(all air above the terrain, all stone below it) doesn't store any indices
+#[derive(Clone, Debug)]
+pub struct PaletteStorage {
+    len: usize,
+    palette: Vec<BlockId>,
+    //bits per index, 0 means every block is palette[0]
+    bits: u32,
+    //indices don't cross word boundaries, the leftover bits are unused
+    words: Vec<u64>,
+}
+
+//bits needed to tell apart `count` palette entries
+fn bits_for(count: usize) -> u32 {
+    if count <= 1 {
+        0
+    } else {
+        usize::BITS - (count - 1).leading_zeros()
+    }
+}
+
+impl PaletteStorage {
+    pub fn new(len: usize, block: BlockId) -> Self {
+        Self {
+            len,
+            palette: vec![block],
+            bits: 0,
+            words: Vec::new(),
+        }
+    }
+
+    pub fn from_slice(blocks: &[BlockId]) -> Self {
+        let mut palette = Vec::new();
+        let indices: Vec<usize> = blocks
+            .iter()
+            .map(|block| match palette.iter().position(|b| b == block) {
+                Some(index) => index,
+                None => {
+                    palette.push(*block);
+                    palette.len() - 1
+                }
+            })
+            .collect();
+        if palette.is_empty() {
+            palette.push(crate::block::AIR);
+        }
+
+        let mut storage = Self {
+            len: blocks.len(),
+            bits: bits_for(palette.len()),
+            palette,
+            words: Vec::new(),
+        };
+        if storage.bits > 0 {
+            storage.words = vec![0; storage.word_count(storage.bits)];
+            for (i, index) in indices.into_iter().enumerate() {
+                storage.write_index(i, index);
+            }
+        }
+        storage
+    }
+
+    pub fn get(&self, index: usize) -> BlockId {
+        debug_assert!(index < self.len);
+        self.palette[self.read_index(index)]
+    }
+
+    pub fn set(&mut self, index: usize, block: BlockId) {
+        debug_assert!(index < self.len);
+        let palette_index = match self.palette.iter().position(|b| *b == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(block);
+                let bits = bits_for(self.palette.len());
+                if bits > self.bits {
+                    self.repack(bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+        if self.bits > 0 {
+            self.write_index(index, palette_index);
+        }
+    }
+
+    //Some when every block is the same, lets callers skip work on empty chunks
+    pub fn single_value(&self) -> Option<BlockId> {
+        (self.bits == 0).then(|| self.palette[0])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = BlockId> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    pub fn to_vec(&self) -> Vec<BlockId> {
+        self.iter().collect()
+    }
+
+    //entries stay in the palette after the last block using them is
+    //overwritten, this drops them and shrinks the indices if it can
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..self.len {
+            used[self.read_index(i)] = true;
+        }
+        if used.iter().all(|u| *u) {
+            return;
+        }
+        *self = Self::from_slice(&self.to_vec());
+    }
+
+    //heap and inline size in bytes
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.palette.capacity() * std::mem::size_of::<BlockId>()
+            + self.words.capacity() * std::mem::size_of::<u64>()
+    }
+
+    fn per_word(bits: u32) -> usize {
+        (u64::BITS / bits) as usize
+    }
+
+    fn word_count(&self, bits: u32) -> usize {
+        if bits == 0 {
+            0
+        } else {
+            self.len.div_ceil(Self::per_word(bits))
+        }
+    }
+
+    fn read_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = Self::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        ((self.words[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn write_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = Self::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !(mask << shift)) | ((palette_index as u64) << shift);
+    }
+
+    fn repack(&mut self, bits: u32) {
+        let indices: Vec<usize> = (0..self.len).map(|i| self.read_index(i)).collect();
+        self.bits = bits;
+        self.words = vec![0; self.word_count(bits)];
+        for (i, index) in indices.into_iter().enumerate() {
+            self.write_index(i, index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::AIR;
+    use crate::coords::{ChunkSize, LocalPos};
+    use crate::noise::position_hash;
+
+    const LEN: usize = ChunkSize::DEFAULT.volume();
+
+    fn random_blocks(kinds: u64, seed: u64) -> Vec<BlockId> {
+        (0..LEN)
+            .map(|i| (position_hash(seed, i as i32, 0, 0) * kinds as f64) as BlockId)
+            .collect()
+    }
+
+    #[test]
+    fn matches_a_flat_array() {
+        for kinds in [1, 2, 3, 5, 17, 300, 4000] {
+            let expected = random_blocks(kinds, kinds);
+            let mut storage = PaletteStorage::new(LEN, AIR);
+            for (i, block) in expected.iter().enumerate() {
+                storage.set(i, *block);
+            }
+            assert_eq!(storage.to_vec(), expected, "{} kinds", kinds);
+            assert_eq!(PaletteStorage::from_slice(&expected).to_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn ids_above_255_fit() {
+        let mut storage = PaletteStorage::new(LEN, AIR);
+        storage.set(10, 300);
+        storage.set(11, BlockId::MAX);
+        assert_eq!(storage.get(10), 300);
+        assert_eq!(storage.get(11), BlockId::MAX);
+        assert_eq!(storage.get(12), AIR);
+    }
+
+    #[test]
+    fn single_value_fast_path() {
+        let mut storage = PaletteStorage::new(LEN, 1);
+        assert_eq!(storage.single_value(), Some(1));
+        //writing the same block again doesn't allocate
+        storage.set(5, 1);
+        assert_eq!(storage.single_value(), Some(1));
+        assert_eq!(
+            storage.memory_usage(),
+            PaletteStorage::new(LEN, 1).memory_usage()
+        );
+
+        storage.set(5, 2);
+        assert_eq!(storage.single_value(), None);
+        storage.set(5, 1);
+        storage.compact();
+        assert_eq!(storage.single_value(), Some(1));
+    }
+
+    #[test]
+    fn memory_per_chunk() {
+        let flat = LEN * std::mem::size_of::<BlockId>();
+        //horizontal layers like flat terrain, stone, dirt and grass under air
+        let layered: Vec<BlockId> = (0..LEN)
+            .map(|i| match LocalPos::from_index(i, ChunkSize::DEFAULT).y {
+                0..=5 => 1,
+                6..=8 => 2,
+                9 => 3,
+                _ => AIR,
+            })
+            .collect();
+        let cases = [
+            (vec![AIR; LEN], 0),
+            (layered, 2),
+            (random_blocks(16, 1), 4),
+            (random_blocks(200, 2), 8),
+        ];
+
+        for (blocks, bits) in cases {
+            let storage = PaletteStorage::from_slice(&blocks);
+            assert_eq!(storage.bits, bits);
+            //the packed indices plus a palette of at most a few hundred ids
+            let bytes = storage.memory_usage();
+            assert!(bytes <= flat * bits as usize / 16 + 1024, "{} bytes", bytes);
+        }
+        assert!(PaletteStorage::new(LEN, AIR).memory_usage() < 128);
+    }
+}
@@ -0,0 +1,365 @@
+use cgmath::{Point3, Vector3};
+
+use crate::coords::WorldPos;
+
+//blocks per second squared
+const GRAVITY: f32 = 25.0;
+//falling doesn't get faster than this, in blocks per second
+const TERMINAL_VELOCITY: f32 = 50.0;
+//a bit more than a block high
+const JUMP_SPEED: f32 = 8.0;
+//ledges this high are walked onto without jumping
+const STEP_HEIGHT: f32 = 1.0;
+//the body is a box around the position
+const HALF_WIDTH: f32 = 0.3;
+const HEIGHT: f32 = 1.8;
+//from the feet
+pub const EYE_HEIGHT: f32 = 1.6;
+pub const WALK_SPEED: f32 = 4.5;
+//boxes that only touch a block don't overlap it
+const EPSILON: f32 = 1e-4;
+
+//what the player wants to do for one update
+#[derive(Clone, Copy, Debug)]
+pub struct PlayerInput {
+    //horizontal velocity, y is ignored
+    pub walk: Vector3<f32>,
+    //only does something while standing on the ground
+    pub jump: bool,
+}
+
+//standing still
+impl Default for PlayerInput {
+    fn default() -> Self {
+        Self {
+            walk: Vector3::new(0.0, 0.0, 0.0),
+            jump: false,
+        }
+    }
+}
+
+//the body the walking camera modes are attached to
+pub struct Player {
+    //the middle of the bottom of the body
+    pub position: Point3<f32>,
+    pub velocity: Vector3<f32>,
+    pub on_ground: bool,
+}
+
+impl Player {
+    pub fn new(position: Point3<f32>) -> Self {
+        Self {
+            position,
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            on_ground: false,
+        }
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.position + Vector3::new(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    //every block the body overlaps
+    pub fn blocks(&self) -> Vec<WorldPos> {
+        let aabb = self.aabb();
+        let mut blocks = Vec::new();
+        for x in aabb.blocks(0) {
+            for y in aabb.blocks(1) {
+                for z in aabb.blocks(2) {
+                    blocks.push(WorldPos::new(x, y, z));
+                }
+            }
+        }
+        blocks
+    }
+
+    fn aabb(&self) -> Aabb {
+        Aabb {
+            min: self.position + Vector3::new(-HALF_WIDTH, 0.0, -HALF_WIDTH),
+            max: self.position + Vector3::new(HALF_WIDTH, HEIGHT, HALF_WIDTH),
+        }
+    }
+
+    //moves the body one axis at a time, y first so stepping up knows if
+    //the player stands on something
+    pub fn update(&mut self, input: PlayerInput, is_solid: impl Fn(WorldPos) -> bool, dt: f32) {
+        self.velocity.x = input.walk.x;
+        self.velocity.z = input.walk.z;
+        if input.jump && self.on_ground {
+            self.velocity.y = JUMP_SPEED;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+
+        let fall = self.velocity.y * dt;
+        let moved = self.sweep(1, fall, &is_solid);
+        self.on_ground = fall < 0.0 && moved > fall;
+        if moved != fall {
+            self.velocity.y = 0.0;
+        }
+
+        for axis in [0, 2] {
+            let distance = self.velocity[axis] * dt;
+            let mut moved = self.sweep(axis, distance, &is_solid);
+            if moved != distance && self.on_ground {
+                moved = self.step_up(axis, moved, distance, &is_solid);
+            }
+            if moved != distance {
+                self.velocity[axis] = 0.0;
+            }
+        }
+    }
+
+    //moves along one axis until the box runs into a solid block, returns
+    //how far it got. every block between the start and the end is checked,
+    //so fast bodies can't pass through thin walls
+    fn sweep(&mut self, axis: usize, distance: f32, is_solid: &impl Fn(WorldPos) -> bool) -> f32 {
+        let aabb = self.aabb();
+        let moved = aabb.sweep(axis, distance, is_solid);
+        self.position[axis] += moved;
+        moved
+    }
+
+    //tries the blocked move again from a block higher and drops back down
+    //onto the ledge, kept only if it gets further
+    fn step_up(
+        &mut self,
+        axis: usize,
+        moved: f32,
+        distance: f32,
+        is_solid: &impl Fn(WorldPos) -> bool,
+    ) -> f32 {
+        let blocked = self.position;
+        if self.sweep(1, STEP_HEIGHT, is_solid) != STEP_HEIGHT {
+            self.position = blocked;
+            return moved;
+        }
+        let stepped = moved + self.sweep(axis, distance - moved, is_solid);
+        if stepped.abs() <= moved.abs() + EPSILON {
+            self.position = blocked;
+            return moved;
+        }
+        self.sweep(1, -STEP_HEIGHT, is_solid);
+        stepped
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+    min: Point3<f32>,
+    max: Point3<f32>,
+}
+
+impl Aabb {
+    //the blocks overlapping the box on one axis
+    fn blocks(&self, axis: usize) -> std::ops::RangeInclusive<i32> {
+        let min = (self.min[axis] + EPSILON).floor() as i32;
+        let max = (self.max[axis] - EPSILON).floor() as i32;
+        min..=max
+    }
+
+    //walks the layers of blocks in front of the moving face one by one and
+    //stops at the first layer with a solid block in it
+    fn sweep(&self, axis: usize, distance: f32, is_solid: &impl Fn(WorldPos) -> bool) -> f32 {
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let layer_is_solid = |layer: i32| {
+            self.blocks(a).any(|i| {
+                self.blocks(b).any(|j| {
+                    let mut pos = [0; 3];
+                    pos[axis] = layer;
+                    pos[a] = i;
+                    pos[b] = j;
+                    is_solid(WorldPos::new(pos[0], pos[1], pos[2]))
+                })
+            })
+        };
+
+        if distance > 0.0 {
+            let face = self.max[axis];
+            let mut layer = (face - EPSILON).ceil() as i32;
+            while (layer as f32) < face + distance {
+                if layer_is_solid(layer) {
+                    return (layer as f32 - face).min(distance);
+                }
+                layer += 1;
+            }
+        } else if distance < 0.0 {
+            let face = self.min[axis];
+            let mut layer = (face + EPSILON).floor() as i32 - 1;
+            while (layer + 1) as f32 > face + distance {
+                if layer_is_solid(layer) {
+                    return (layer as f32 + 1.0 - face).max(distance);
+                }
+                layer -= 1;
+            }
+        }
+        distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    //blocks set by hand with a floor at y = 0, the top of the floor is y = 1
+    struct Blocks(HashSet<WorldPos>);
+
+    impl Blocks {
+        fn floor() -> Self {
+            let mut blocks = HashSet::new();
+            for x in -10..10 {
+                for z in -10..10 {
+                    blocks.insert(WorldPos::new(x, 0, z));
+                }
+            }
+            Self(blocks)
+        }
+
+        fn with(mut self, x: i32, y: i32, z: i32) -> Self {
+            self.0.insert(WorldPos::new(x, y, z));
+            self
+        }
+
+        //runs the same input for a number of fixed steps
+        fn run(&self, player: &mut Player, input: PlayerInput, steps: usize) {
+            for _ in 0..steps {
+                player.update(input, |pos| self.0.contains(&pos), DT);
+            }
+        }
+    }
+
+    fn walk(x: f32, z: f32) -> PlayerInput {
+        PlayerInput {
+            walk: Vector3::new(x, 0.0, z),
+            jump: false,
+        }
+    }
+
+    #[test]
+    fn body_blocks() {
+        let player = Player::new(Point3::new(0.5, 1.0, 0.5));
+        assert_eq!(
+            player.blocks(),
+            vec![WorldPos::new(0, 1, 0), WorldPos::new(0, 2, 0)]
+        );
+
+        //standing on a corner overlaps the blocks on all four sides of it
+        let player = Player::new(Point3::new(1.0, 1.0, 1.0));
+        let blocks = player.blocks();
+        assert_eq!(blocks.len(), 8);
+        for pos in [
+            WorldPos::new(0, 1, 0),
+            WorldPos::new(1, 2, 1),
+            WorldPos::new(0, 2, 1),
+        ] {
+            assert!(blocks.contains(&pos), "{:?}", pos);
+        }
+    }
+
+    #[test]
+    fn falls_and_lands_on_the_ground() {
+        let blocks = Blocks::floor();
+        let mut player = Player::new(Point3::new(0.5, 5.0, 0.5));
+        blocks.run(&mut player, PlayerInput::default(), 120);
+        assert!(player.on_ground);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+        assert_eq!(player.velocity.y, 0.0);
+
+        //walking keeps it on the ground
+        blocks.run(&mut player, walk(WALK_SPEED, 0.0), 30);
+        assert!(player.on_ground);
+        assert!((player.position.x - (0.5 + WALK_SPEED * 0.5)).abs() < 1e-3);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fast_falls_dont_pass_through_the_floor() {
+        let blocks = Blocks::floor();
+        let mut player = Player::new(Point3::new(0.5, 200.0, 0.5));
+        player.velocity.y = -TERMINAL_VELOCITY;
+        //a single long step covers many blocks
+        player.update(PlayerInput::default(), |pos| blocks.0.contains(&pos), 10.0);
+        assert!(player.on_ground);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn jumps_about_a_block_high() {
+        let blocks = Blocks::floor();
+        let mut player = Player::new(Point3::new(0.5, 1.0, 0.5));
+        blocks.run(&mut player, PlayerInput::default(), 1);
+        assert!(player.on_ground);
+
+        let jump = PlayerInput {
+            jump: true,
+            ..Default::default()
+        };
+        blocks.run(&mut player, jump, 1);
+        let mut highest = player.position.y;
+        for _ in 0..60 {
+            blocks.run(&mut player, PlayerInput::default(), 1);
+            highest = highest.max(player.position.y);
+        }
+        assert!(highest > 2.2 && highest < 2.5, "{}", highest);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+
+        //jumping in the air does nothing
+        let mut player = Player::new(Point3::new(0.5, 5.0, 0.5));
+        blocks.run(&mut player, jump, 1);
+        assert!(player.velocity.y < 0.0);
+    }
+
+    #[test]
+    fn ceilings_stop_jumps() {
+        //two blocks of room, the body is 1.8 high
+        let blocks = Blocks::floor().with(0, 3, 0);
+        let mut player = Player::new(Point3::new(0.5, 1.0, 0.5));
+        blocks.run(&mut player, PlayerInput::default(), 1);
+        let jump = PlayerInput {
+            jump: true,
+            ..Default::default()
+        };
+        for _ in 0..30 {
+            blocks.run(&mut player, jump, 1);
+            assert!(player.position.y + HEIGHT <= 3.0 + 1e-4);
+        }
+    }
+
+    #[test]
+    fn walls_stop_the_body_flush() {
+        //two blocks high, too high to step onto
+        let mut blocks = Blocks::floor();
+        for z in -10..10 {
+            blocks = blocks.with(3, 1, z).with(3, 2, z);
+        }
+        let mut player = Player::new(Point3::new(0.5, 1.0, 0.5));
+        blocks.run(&mut player, walk(WALK_SPEED, 0.0), 60);
+        assert!((player.position.x - (3.0 - HALF_WIDTH)).abs() < 1e-4);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+        assert_eq!(player.velocity.x, 0.0);
+
+        //sliding along the wall still works
+        blocks.run(&mut player, walk(WALK_SPEED, WALK_SPEED), 30);
+        assert!((player.position.x - (3.0 - HALF_WIDTH)).abs() < 1e-4);
+        assert!(player.position.z > 2.0);
+    }
+
+    #[test]
+    fn steps_up_onto_ledges() {
+        let blocks = Blocks::floor().with(2, 1, 0).with(3, 1, 0).with(4, 1, 0);
+        let mut player = Player::new(Point3::new(0.5, 1.0, 0.5));
+        blocks.run(&mut player, walk(WALK_SPEED, 0.0), 40);
+        assert!(player.position.x > 3.0);
+        assert!(player.on_ground);
+        assert!((player.position.y - 2.0).abs() < 1e-4);
+
+        //no room above the ledge
+        let blocks = Blocks::floor().with(2, 1, 0).with(1, 3, 0).with(2, 3, 0);
+        let mut player = Player::new(Point3::new(0.5, 1.0, 0.5));
+        blocks.run(&mut player, walk(WALK_SPEED, 0.0), 40);
+        assert!((player.position.x - (2.0 - HALF_WIDTH)).abs() < 1e-4);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,225 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::block::{BlockId, AIR};
+use crate::coords::WorldPos;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub block: BlockId,
+    pub pos: WorldPos,
+    //the face the ray entered through, the block in front of it is pos + normal.
+    //zero when the ray starts inside of a block
+    pub normal: Vector3<i32>,
+    //along the ray, from its start to where it enters the block
+    pub distance: f32,
+}
+
+//walks the blocks the ray passes through in order and returns the first
+//one that isn't air (Amanatides & Woo, "A Fast Voxel Traversal Algorithm").
+//every block is visited once, so thin walls and corners can't be skipped
+pub fn raycast(
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+    block_at: impl Fn(WorldPos) -> BlockId,
+) -> Option<RayHit> {
+    if direction.magnitude2() == 0.0 || !direction.magnitude2().is_finite() {
+        return None;
+    }
+    let direction = direction.normalize();
+    let start = WorldPos::from_point(origin);
+    let mut pos = [start.x, start.y, start.z];
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+
+    let mut step = [0; 3];
+    //distance along the ray to the next block border on every axis
+    let mut next_border = [f32::INFINITY; 3];
+    //distance along the ray between two borders of an axis
+    let mut border_spacing = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            let border = pos[axis] as f32 + 1.0;
+            next_border[axis] = (border - origin[axis]) / direction[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            let border = pos[axis] as f32;
+            next_border[axis] = (border - origin[axis]) / direction[axis];
+        }
+        if step[axis] != 0 {
+            border_spacing[axis] = 1.0 / direction[axis].abs();
+        }
+    }
+
+    let mut normal = Vector3::new(0, 0, 0);
+    let mut distance = 0.0;
+    loop {
+        let world = WorldPos::new(pos[0], pos[1], pos[2]);
+        let block = block_at(world);
+        if block != AIR {
+            return Some(RayHit {
+                block,
+                pos: world,
+                normal,
+                distance,
+            });
+        }
+
+        let axis = (0..3)
+            .min_by(|a, b| next_border[*a].total_cmp(&next_border[*b]))
+            .unwrap();
+        distance = next_border[axis];
+        if distance > max_distance {
+            return None;
+        }
+        pos[axis] += step[axis];
+        next_border[axis] += border_spacing[axis];
+        normal = Vector3::new(0, 0, 0);
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkMeshData;
+    use crate::coords::{ChunkPos, ChunkSize};
+    use crate::noise::position_hash;
+    use std::collections::HashMap;
+
+    const SIZE: ChunkSize = ChunkSize::DEFAULT;
+
+    //chunks filled by hand, unloaded ones are air
+    struct Blocks(HashMap<ChunkPos, ChunkMeshData>);
+
+    impl Blocks {
+        fn new() -> Self {
+            Self(HashMap::new())
+        }
+
+        fn set(&mut self, pos: WorldPos, block: BlockId) {
+            let (chunk, local) = pos.to_chunk(SIZE);
+            self.0
+                .entry(chunk)
+                .or_insert_with(|| ChunkMeshData::new(chunk, SIZE))
+                .set_block(local.x, local.y, local.z, block);
+        }
+
+        fn get(&self, pos: WorldPos) -> BlockId {
+            let (chunk, local) = pos.to_chunk(SIZE);
+            self.0
+                .get(&chunk)
+                .map_or(AIR, |chunk| chunk.get_block(local.x, local.y, local.z))
+        }
+
+        fn cast(&self, origin: [f32; 3], direction: [f32; 3], max: f32) -> Option<RayHit> {
+            raycast(origin.into(), direction.into(), max, |pos| self.get(pos))
+        }
+    }
+
+    #[test]
+    fn hits_the_face_it_enters() {
+        let mut blocks = Blocks::new();
+        blocks.set(WorldPos::new(5, 0, 0), 1);
+        blocks.set(WorldPos::new(0, -3, 0), 2);
+
+        let hit = blocks.cast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 10.0).unwrap();
+        assert_eq!(hit.pos, WorldPos::new(5, 0, 0));
+        assert_eq!(hit.block, 1);
+        assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+
+        let hit = blocks
+            .cast([0.5, 0.5, 0.5], [0.0, -1.0, 0.0], 10.0)
+            .unwrap();
+        assert_eq!(hit.pos, WorldPos::new(0, -3, 0));
+        assert_eq!(hit.normal, Vector3::new(0, 1, 0));
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        //too far away
+        assert_eq!(blocks.cast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 4.0), None);
+        assert_eq!(blocks.cast([0.5, 0.5, 0.5], [0.0, 1.0, 0.0], 50.0), None);
+        assert_eq!(blocks.cast([0.5, 0.5, 0.5], [0.0, 0.0, 0.0], 50.0), None);
+    }
+
+    #[test]
+    fn crosses_chunk_borders_and_negative_coordinates() {
+        let mut blocks = Blocks::new();
+        blocks.set(WorldPos::new(-20, 3, -20), 7);
+        //the ray goes through the middle of the block diagonally
+        let hit = blocks
+            .cast([0.5, 3.5, 0.5], [-1.0, 0.0, -1.0], 100.0)
+            .unwrap();
+        assert_eq!(hit.pos, WorldPos::new(-20, 3, -20));
+        assert_eq!(hit.block, 7);
+        assert!(hit.normal == Vector3::new(1, 0, 0) || hit.normal == Vector3::new(0, 0, 1));
+        let expected = 19.5 * std::f32::consts::SQRT_2;
+        assert!((hit.distance - expected).abs() < 0.01, "{}", hit.distance);
+    }
+
+    #[test]
+    fn starting_inside_a_block() {
+        let mut blocks = Blocks::new();
+        blocks.set(WorldPos::new(0, 0, 0), 3);
+        let hit = blocks.cast([0.2, 0.7, 0.9], [0.3, 1.0, 0.0], 10.0).unwrap();
+        assert_eq!(hit.pos, WorldPos::new(0, 0, 0));
+        assert_eq!(hit.normal, Vector3::new(0, 0, 0));
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    //every block the ray visits has to touch the ray, and the hit has to
+    //be the closest solid block along it
+    #[test]
+    fn matches_a_brute_force_march() {
+        let mut blocks = Blocks::new();
+        let mut i = 0;
+        let mut next = || {
+            i += 1;
+            position_hash(42, i, 0, 0) as f32
+        };
+        for _ in 0..400 {
+            let pos = WorldPos::new(
+                (next() * 24.0) as i32 - 12,
+                (next() * 24.0) as i32 - 12,
+                (next() * 24.0) as i32 - 12,
+            );
+            blocks.set(pos, 1);
+        }
+
+        for _ in 0..150 {
+            let origin = [next() * 4.0 - 2.0, next() * 4.0 - 2.0, next() * 4.0 - 2.0];
+            let direction = Vector3::new(next() - 0.5, next() - 0.5, next() - 0.5);
+            if blocks.get(WorldPos::from_point(origin.into())) != AIR {
+                continue;
+            }
+            let hit = blocks.cast(origin, direction.into(), 30.0);
+
+            //tiny steps along the ray find the same block unless the ray
+            //only clips an edge of it
+            let direction = direction.normalize();
+            let mut marched = None;
+            let mut t = 0.0;
+            while t < 30.0 {
+                let point = Point3::from(origin) + direction * t;
+                let pos = WorldPos::from_point(point);
+                if blocks.get(pos) != AIR {
+                    marched = Some((pos, t));
+                    break;
+                }
+                t += 0.002;
+            }
+            match (hit, marched) {
+                (Some(hit), Some((pos, t))) => {
+                    assert!(hit.distance <= t + 1e-3, "{:?} {:?}", hit, pos);
+                    assert!(t - hit.distance < 0.01 || hit.pos != pos);
+                    //the block in front of the hit face is air
+                    assert_eq!(blocks.get(hit.pos + hit.normal), AIR);
+                }
+                (None, Some((pos, t))) => panic!("missed {:?} at {}", pos, t),
+                (Some(hit), None) => assert!(hit.distance < 30.0),
+                (None, None) => {}
+            }
+        }
+    }
+}
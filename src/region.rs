@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use cgmath::Vector3;
+
+use crate::chunk::ChunkMeshData;
+use crate::coords::{ChunkPos, ChunkSize};
+use crate::serialize;
+
+//a region file stores REGION_SIZE x REGION_SIZE chunks of a single chunk layer
+pub const REGION_SIZE: i32 = 32;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+//file layout, every number is little endian:
+//  magic "VXRG", version u32
+//  offset table, CHUNKS_PER_REGION entries of (offset u32, length u32),
+//  indexed by x + z * REGION_SIZE, a length of 0 means the chunk was never saved
+//  every saved chunk, encoded by serialize::encode and deflate compressed
+const MAGIC: &[u8; 4] = b"VXRG";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8 + CHUNKS_PER_REGION * 8;
+
+//regions get read the first time one of their chunks is requested and
+//are kept in memory (still compressed) until they get evicted. the lock
+//is only held to look chunks up, reading and writing files and
+//(de)compressing happens outside of it so the main thread and the
+//workers don't wait on each other's disk access
+pub struct RegionStorage {
+    dir: PathBuf,
+    //every chunk of a save has the same size, loading a chunk of another size fails
+    chunk_size: ChunkSize,
+    regions: Mutex<HashMap<Vector3<i32>, Region>>,
+    //one flush at a time, so an older copy of a region can't be written
+    //over a newer one or evicted before it's on disk
+    flushing: Mutex<()>,
+}
+
+struct Region {
+    chunks: Vec<Option<Vec<u8>>>,
+    //has chunks that aren't written to disk yet
+    dirty: bool,
+}
+
+//region coordinates and the index of the chunk inside of the region
+pub fn region_coords(chunk: ChunkPos) -> (Vector3<i32>, usize) {
+    let region = Vector3::new(
+        chunk.x.div_euclid(REGION_SIZE),
+        chunk.y,
+        chunk.z.div_euclid(REGION_SIZE),
+    );
+    let index = chunk.x.rem_euclid(REGION_SIZE) + chunk.z.rem_euclid(REGION_SIZE) * REGION_SIZE;
+    (region, index as usize)
+}
+
+impl RegionStorage {
+    pub fn new(dir: impl Into<PathBuf>, chunk_size: ChunkSize) -> Self {
+        Self {
+            dir: dir.into(),
+            chunk_size,
+            regions: Mutex::new(HashMap::new()),
+            flushing: Mutex::new(()),
+        }
+    }
+
+    //None if the chunk was never saved
+    pub fn load_chunk(&self, coords: ChunkPos) -> anyhow::Result<Option<ChunkMeshData>> {
+        let (region, index) = region_coords(coords);
+        let Some(compressed) = self.with_region(region, |region| region.chunks[index].clone())?
+        else {
+            return Ok(None);
+        };
+
+        //anything bigger than the worst case encoding is corrupted
+        let limit = serialize::max_encoded_len(self.chunk_size);
+        let bytes = miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, limit)
+            .map_err(|e| anyhow::anyhow!("chunk {:?} is corrupted: {:?}", coords, e.status))?;
+
+        let mut chunk = ChunkMeshData::new(coords, self.chunk_size);
+        chunk.chunk_data = serialize::decode(&bytes, self.chunk_size)
+            .map_err(|e| anyhow::anyhow!("chunk {:?} is corrupted: {}", coords, e))?;
+        Ok(Some(chunk))
+    }
+
+    //the chunk only ends up on disk after the next flush
+    pub fn save_chunk(&self, chunk: &ChunkMeshData) -> anyhow::Result<()> {
+        let (region, index) = region_coords(chunk.position());
+        let bytes = serialize::encode(&chunk.chunk_data, chunk.size());
+        let compressed = miniz_oxide::deflate::compress_to_vec(&bytes, 6);
+        self.with_region(region, |region| {
+            region.chunks[index] = Some(compressed);
+            region.dirty = true;
+        })
+    }
+
+    //writes every region with unsaved chunks
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let _flushing = self.flushing.lock().unwrap();
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow::anyhow!("couldn't create {}: {}", self.dir.display(), e))?;
+        let dirty: Vec<(Vector3<i32>, Vec<u8>)> = self
+            .regions
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|(_, region)| region.dirty)
+            .map(|(coords, region)| {
+                region.dirty = false;
+                (*coords, region_bytes(region))
+            })
+            .collect();
+
+        for (i, (coords, bytes)) in dirty.iter().enumerate() {
+            if let Err(e) = write_region(&region_path(&self.dir, *coords), bytes) {
+                //the next flush tries again
+                let mut regions = self.regions.lock().unwrap();
+                for (coords, _) in &dirty[i..] {
+                    if let Some(region) = regions.get_mut(coords) {
+                        region.dirty = true;
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    //forgets the cached regions keep returns false for, regions with
+    //unsaved chunks always stay
+    pub fn evict(&self, keep: impl Fn(Vector3<i32>) -> bool) {
+        let _flushing = self.flushing.lock().unwrap();
+        self.regions
+            .lock()
+            .unwrap()
+            .retain(|coords, region| region.dirty || keep(*coords));
+    }
+
+    #[cfg(test)]
+    fn cached_regions(&self) -> usize {
+        self.regions.lock().unwrap().len()
+    }
+
+    //a region that isn't cached yet is read without holding the lock,
+    //if another thread was quicker its copy is used
+    fn with_region<T>(
+        &self,
+        coords: Vector3<i32>,
+        f: impl FnOnce(&mut Region) -> T,
+    ) -> anyhow::Result<T> {
+        if let Some(region) = self.regions.lock().unwrap().get_mut(&coords) {
+            return Ok(f(region));
+        }
+        let region = read_region(&region_path(&self.dir, coords))?;
+        let mut regions = self.regions.lock().unwrap();
+        Ok(f(regions.entry(coords).or_insert(region)))
+    }
+}
+
+fn region_path(dir: &Path, coords: Vector3<i32>) -> PathBuf {
+    dir.join(format!("r.{}.{}.{}.region", coords.x, coords.y, coords.z))
+}
+
+//a missing file is an empty region
+fn read_region(path: &Path) -> anyhow::Result<Region> {
+    let mut region = Region {
+        chunks: vec![None; CHUNKS_PER_REGION],
+        dirty: false,
+    };
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(region),
+        Err(e) => anyhow::bail!("couldn't read {}: {}", path.display(), e),
+    };
+
+    if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+        anyhow::bail!("{} isn't a region file", path.display());
+    }
+    let read_u32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let version = read_u32(4);
+    if version != VERSION {
+        anyhow::bail!(
+            "{} has version {}, expected {}",
+            path.display(),
+            version,
+            VERSION
+        );
+    }
+
+    for (index, chunk) in region.chunks.iter_mut().enumerate() {
+        let entry = 8 + index * 8;
+        let (offset, length) = (read_u32(entry) as usize, read_u32(entry + 4) as usize);
+        if length == 0 {
+            continue;
+        }
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}: chunk {} points outside of the file",
+                    path.display(),
+                    index
+                )
+            })?;
+        *chunk = Some(data.to_vec());
+    }
+    Ok(region)
+}
+
+fn region_bytes(region: &Region) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut offset = HEADER_SIZE;
+    for chunk in &region.chunks {
+        let length = chunk.as_ref().map_or(0, |data| data.len());
+        let entry_offset = if length == 0 { 0 } else { offset };
+        bytes.extend_from_slice(&(entry_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        offset += length;
+    }
+    for data in region.chunks.iter().flatten() {
+        bytes.extend_from_slice(data);
+    }
+    bytes
+}
+
+//written next to the old file and renamed over it so a crash while
+//saving can't leave a half written region behind
+fn write_region(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    let write = || -> std::io::Result<()> {
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    };
+    write().map_err(|e| anyhow::anyhow!("couldn't write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockId;
+    use crate::palette::PaletteStorage;
+
+    const SIZE: ChunkSize = ChunkSize::DEFAULT;
+
+    //a fresh directory per test so they can run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("voxel_region_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn test_chunk(coords: ChunkPos, seed: BlockId) -> ChunkMeshData {
+        let mut chunk = ChunkMeshData::new(coords, SIZE);
+        let blocks: Vec<BlockId> = (0..SIZE.volume())
+            //mostly runs with some noise, like real terrain
+            .map(|i| {
+                if i % 7 == 0 {
+                    seed
+                } else {
+                    (i / 300) as BlockId
+                }
+            })
+            .collect();
+        chunk.chunk_data = PaletteStorage::from_slice(&blocks);
+        chunk
+    }
+
+    #[test]
+    fn chunks_survive_a_round_trip() {
+        let dir = temp_dir("round_trip");
+        let coords = [
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(31, 0, 31),
+            ChunkPos::new(32, 0, 0),
+            ChunkPos::new(-1, 0, -33),
+            ChunkPos::new(5, -2, 7),
+        ];
+
+        let storage = RegionStorage::new(&dir, SIZE);
+        for (i, &c) in coords.iter().enumerate() {
+            storage.save_chunk(&test_chunk(c, i as BlockId)).unwrap();
+        }
+        storage.flush().unwrap();
+
+        let storage = RegionStorage::new(&dir, SIZE);
+        for (i, &c) in coords.iter().enumerate() {
+            let chunk = storage.load_chunk(c).unwrap().expect("chunk wasn't saved");
+            assert_eq!(
+                chunk.chunk_data.to_vec(),
+                test_chunk(c, i as BlockId).chunk_data.to_vec()
+            );
+            assert_eq!(chunk.position(), c);
+        }
+        assert!(storage
+            .load_chunk(ChunkPos::new(1, 0, 0))
+            .unwrap()
+            .is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //every block differs from its neighbour, that doesn't compress into 64 KiB
+    #[test]
+    fn big_noisy_chunks_load() {
+        let dir = temp_dir("big_chunks");
+        let size = ChunkSize::new(32, 32, 32);
+        let mut chunk = ChunkMeshData::new(ChunkPos::new(0, 0, 0), size);
+        let blocks: Vec<BlockId> = (0..size.volume())
+            .map(|i| (i % 2 * 1000) as BlockId)
+            .collect();
+        chunk.chunk_data = PaletteStorage::from_slice(&blocks);
+
+        let storage = RegionStorage::new(&dir, size);
+        storage.save_chunk(&chunk).unwrap();
+        storage.flush().unwrap();
+        let storage = RegionStorage::new(&dir, size);
+        let loaded = storage.load_chunk(chunk.position()).unwrap().unwrap();
+        assert_eq!(loaded.chunk_data.to_vec(), blocks);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saving_again_replaces_the_chunk() {
+        let dir = temp_dir("replace");
+        let (a, b) = (ChunkPos::new(3, 0, 4), ChunkPos::new(4, 0, 4));
+
+        let storage = RegionStorage::new(&dir, SIZE);
+        storage.save_chunk(&test_chunk(a, 1)).unwrap();
+        storage.save_chunk(&test_chunk(b, 2)).unwrap();
+        storage.flush().unwrap();
+
+        let storage = RegionStorage::new(&dir, SIZE);
+        storage.save_chunk(&test_chunk(a, 9)).unwrap();
+        storage.flush().unwrap();
+
+        let storage = RegionStorage::new(&dir, SIZE);
+        let load = |storage: &RegionStorage, c| storage.load_chunk(c).unwrap().unwrap();
+        assert_eq!(
+            load(&storage, a).chunk_data.to_vec(),
+            test_chunk(a, 9).chunk_data.to_vec()
+        );
+        assert_eq!(
+            load(&storage, b).chunk_data.to_vec(),
+            test_chunk(b, 2).chunk_data.to_vec()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_saved_regions_get_evicted() {
+        let dir = temp_dir("evict");
+        let (near, far) = (ChunkPos::new(0, 0, 0), ChunkPos::new(100, 0, 0));
+        let storage = RegionStorage::new(&dir, SIZE);
+        storage.save_chunk(&test_chunk(near, 1)).unwrap();
+        storage.save_chunk(&test_chunk(far, 2)).unwrap();
+
+        //nothing is on disk yet
+        storage.evict(|_| false);
+        assert_eq!(storage.cached_regions(), 2);
+
+        storage.flush().unwrap();
+        let (near_region, _) = region_coords(near);
+        storage.evict(|region| region == near_region);
+        assert_eq!(storage.cached_regions(), 1);
+
+        //read back from the file
+        let chunk = storage.load_chunk(far).unwrap().unwrap();
+        assert_eq!(
+            chunk.chunk_data.to_vec(),
+            test_chunk(far, 2).chunk_data.to_vec()
+        );
+        assert_eq!(storage.cached_regions(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn workers_share_the_storage() {
+        let dir = temp_dir("threads");
+        let storage = std::sync::Arc::new(RegionStorage::new(&dir, SIZE));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        let coords = ChunkPos::new(i * 7, t, -i);
+                        storage
+                            .save_chunk(&test_chunk(coords, t as BlockId))
+                            .unwrap();
+                        if i % 5 == 0 {
+                            storage.flush().unwrap();
+                            storage.evict(|_| false);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        storage.flush().unwrap();
+
+        let storage = RegionStorage::new(&dir, SIZE);
+        for t in 0..4 {
+            for i in 0..20 {
+                let coords = ChunkPos::new(i * 7, t, -i);
+                let chunk = storage.load_chunk(coords).unwrap().expect("chunk was lost");
+                assert_eq!(
+                    chunk.chunk_data.to_vec(),
+                    test_chunk(coords, t as BlockId).chunk_data.to_vec()
+                );
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        let dir = temp_dir("broken");
+        let coords = ChunkPos::new(0, 0, 0);
+        let storage = RegionStorage::new(&dir, SIZE);
+        storage.save_chunk(&test_chunk(coords, 1)).unwrap();
+        storage.flush().unwrap();
+
+        let (region, _) = region_coords(coords);
+        let path = region_path(&dir, region);
+        let bytes = std::fs::read(&path).unwrap();
+
+        //cut off in the middle of the chunk data
+        std::fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(RegionStorage::new(&dir, SIZE).load_chunk(coords).is_err());
+
+        //garbage instead of compressed data
+        let mut garbage = bytes.clone();
+        for byte in &mut garbage[HEADER_SIZE..] {
+            *byte = 0xFF;
+        }
+        std::fs::write(&path, &garbage).unwrap();
+        assert!(RegionStorage::new(&dir, SIZE).load_chunk(coords).is_err());
+
+        std::fs::write(&path, b"not a region").unwrap();
+        assert!(RegionStorage::new(&dir, SIZE).load_chunk(coords).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
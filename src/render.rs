@@ -1,11 +1,19 @@
 use std::iter;
 use wgpu::util::DeviceExt;
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
 
+use crate::block;
 use crate::camera::{Camera, Projection};
 use crate::chunk;
+use crate::coords::{ChunkSize, WorldPos};
+use crate::player::Player;
+use crate::raycast::RayHit;
 use crate::depth_texture;
-use crate::egui_integration;
+use crate::structure;
+use crate::terrain;
+use crate::world;
 
 pub struct Render {
     surface: wgpu::Surface,
@@ -22,16 +30,32 @@ pub struct Render {
     camera_uniform: CameraUniform,
     camera_bind_group: wgpu::BindGroup,
 
-    chunks: Vec<chunk::ChunkMeshData>,
+    world: world::World,
+    frame_stats: FrameStats,
     depth_texture: depth_texture::DepthTexture,
 
+    #[allow(dead_code)]
     light_buffer: wgpu::Buffer,
+    #[allow(dead_code)]
     light_uniform: LightUniform,
     light_bind_group: wgpu::BindGroup,
 
     light_object_vertex_buffer: wgpu::Buffer,
     light_object_index_buffer: wgpu::Buffer,
     light_pipeline: wgpu::RenderPipeline,
+
+    //the block the camera looks at, outlined and edited with the mouse
+    target: Option<RayHit>,
+    //the blocks the camera and the player's body are in, nothing gets placed there
+    occupied: Vec<WorldPos>,
+    //blocks right click cycles through with the mouse wheel
+    placeable: Vec<block::BlockId>,
+    selected: usize,
+    //scrolling that didn't add up to a whole step yet
+    scroll: f32,
+    outline_vertex_buffer: wgpu::Buffer,
+    outline_index_buffer: wgpu::Buffer,
+    outline_pipeline: wgpu::RenderPipeline,
 }
 
 #[repr(C)]
@@ -43,9 +67,62 @@ struct LightUniform {
     _padding2: u32,
 }
 
+//gpu traffic of the last rendered frame
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameStats {
+    pub bytes_uploaded: u64,
+    pub chunks_uploaded: u32,
+    pub chunks_loaded: usize,
+    //block data of the loaded chunks, meshes not included
+    pub block_memory: usize,
+}
+
+impl std::fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "uploaded {} bytes for {} chunks, {} chunks use {} bytes of block data",
+            self.bytes_uploaded, self.chunks_uploaded, self.chunks_loaded, self.block_memory
+        )
+    }
+}
+
+const WORLD_SEED: u64 = 0x5EED;
+//try ChunkSize::new(32, 32, 32) or tall columns like ChunkSize::new(16, 256, 16)
+const CHUNK_SIZE: ChunkSize = ChunkSize::DEFAULT;
+
+//there's no filesystem on the web, edits are lost on reload there
+#[cfg(not(target_arch = "wasm32"))]
+const SAVE_DIR: Option<&str> = Some("saves/world");
+#[cfg(target_arch = "wasm32")]
+const SAVE_DIR: Option<&str> = None;
+
+const LIGHT_OBJECT_COLOR: [f32; 3] = [0.71, 1.0, 0.34];
+
+//in blocks, how far away blocks can be broken and placed
+const REACH: f32 = 8.0;
+const PLACEABLE_BLOCKS: [&str; 9] = [
+    "stone",
+    "dirt",
+    "grass",
+    "cobblestone",
+    "log",
+    "leaves",
+    "sand",
+    "glass",
+    "glowstone",
+];
+const OUTLINE_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+//how far a touchpad scrolls for one step through the placeable blocks
+const SCROLL_LINE_PIXELS: f32 = 40.0;
+//the outline is a bit bigger than a block so it doesn't z-fight with its faces
+const OUTLINE_GROW: f32 = 0.005;
+
 enum RenderingMode {
     Fill,
     Wireframe,
+    //draws the index buffer as pairs of line ends
+    Lines,
 }
 
 impl Render {
@@ -181,8 +258,13 @@ impl Render {
             label: None,
         });
 
+        let light_block = block::Block {
+            emissive: true,
+            face_colors: [LIGHT_OBJECT_COLOR; 6],
+            ..block::Block::new(block::AIR, "light")
+        };
         let (light_object_vertex_buffer, light_object_index_buffer) =
-            chunk::generate_voxel(&device, 0.0, 15.0, 2.0);
+            chunk::generate_voxel(&device, 0.0, 15.0, 2.0, &light_block);
 
         let light_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
@@ -202,6 +284,32 @@ impl Render {
             RenderingMode::Fill,
         );
 
+        //edges drawn with the unlit light shader, the vertices are rewritten
+        //whenever the targeted block changes
+        let (outline_vertices, outline_indices) = outline_mesh(WorldPos::default());
+        let outline_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("outline vertex buffer"),
+            contents: bytemuck::cast_slice(&outline_vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let outline_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("outline index buffer"),
+            contents: bytemuck::cast_slice(&outline_indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let shader = wgpu::ShaderModuleDescriptor {
+            label: Some("outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
+        };
+        let outline_pipeline = create_render_pipeline(
+            &device,
+            &light_pipeline_layout,
+            config.format,
+            &[chunk::Vertex::desc()],
+            shader,
+            RenderingMode::Lines,
+        );
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
             bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
@@ -226,20 +334,38 @@ impl Render {
         let depth_texture =
             depth_texture::DepthTexture::create_depth_texture(&device, &config, "depth_texture");
 
-        let mut chunks = Vec::new();
-        let width = 1;
-        let mut total_faces: u32 = 0;
-
-        for z in 0..1 {
-            for x in 0..width {
-                let mut chunk =
-                    chunk::ChunkMeshData::new(cgmath::Vector3::<usize>::new(x * 16, 0, z * 16));
-                chunk.generate_data();
-                total_faces += chunk.generate_mesh();
-                chunks.push(chunk);
+        //chunks get loaded around the camera in update
+        let mut block_registry = block::BlockRegistry::load_or_builtin("res/blocks.txt");
+        //an edited blocks.txt can parse fine and still miss a terrain block
+        let mut terrain = match terrain::NoiseTerrain::new(WORLD_SEED, &block_registry) {
+            Ok(terrain) => terrain,
+            Err(e) => {
+                log::warn!("falling back to builtin blocks: {}", e);
+                block_registry = block::BlockRegistry::builtin();
+                terrain::NoiseTerrain::new(WORLD_SEED, &block_registry)
+                    .expect("builtin res/blocks.txt is missing terrain blocks")
             }
-        }
-        println!("total faces {}", total_faces);
+        };
+        let placeable = PLACEABLE_BLOCKS
+            .iter()
+            .filter_map(|name| block_registry.id(name))
+            .collect();
+        terrain.structures =
+            structure::StructureSet::load_or_builtin("res/structures.txt", &block_registry);
+        let world = world::World::new(
+            block_registry,
+            world::WorldConfig {
+                render_distance: 6,
+                vertical_render_distance: 1,
+                chunks_per_frame: 4,
+                meshing_mode: chunk::MeshingMode::Greedy,
+                seed: WORLD_SEED,
+                chunk_size: CHUNK_SIZE,
+                color_variation: true,
+                save_dir: SAVE_DIR.map(std::path::PathBuf::from),
+            },
+            std::sync::Arc::new(terrain),
+        );
 
         /*
         let egui_routine = egui_integration::EguiRenderRoutine::new(
@@ -262,7 +388,8 @@ impl Render {
 
             pipeline,
             pipeline_layout,
-            chunks,
+            world,
+            frame_stats: FrameStats::default(),
             camera_buffer,
             camera_bind_group,
             camera_uniform,
@@ -275,13 +402,30 @@ impl Render {
             light_object_vertex_buffer,
             light_object_index_buffer,
             light_pipeline,
+
+            target: None,
+            occupied: Vec::new(),
+            placeable,
+            selected: 0,
+            scroll: 0.0,
+            outline_vertex_buffer,
+            outline_index_buffer,
+            outline_pipeline,
         }
     }
 
+    pub fn world(&self) -> &world::World {
+        &self.world
+    }
+
     pub fn window(&self) -> &winit::window::Window {
         &self.window
     }
 
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
     pub fn width(&self) -> f32 {
         self.config.width as f32
     }
@@ -323,7 +467,7 @@ impl Render {
                     },
                 ..
             } => {
-                let _is_pressed = *state == ElementState::Pressed;
+                let is_pressed = *state == ElementState::Pressed;
                 match keycode {
                     VirtualKeyCode::Key1 => {
                         let shader = wgpu::ShaderModuleDescriptor {
@@ -355,14 +499,111 @@ impl Render {
                         );
                         true
                     }
+                    VirtualKeyCode::Key3 => {
+                        self.world.set_meshing_mode(chunk::MeshingMode::Naive);
+                        true
+                    }
+                    VirtualKeyCode::Key4 => {
+                        self.world.set_meshing_mode(chunk::MeshingMode::Greedy);
+                        true
+                    }
+                    VirtualKeyCode::Z if is_pressed => {
+                        self.world.undo();
+                        true
+                    }
+                    VirtualKeyCode::X if is_pressed => {
+                        self.world.redo();
+                        true
+                    }
                     _ => false,
                 }
             }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } => match button {
+                MouseButton::Left => {
+                    self.break_block();
+                    true
+                }
+                MouseButton::Right => {
+                    self.place_block();
+                    true
+                }
+                _ => false,
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                //touchpads send lots of small pixel deltas
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        position.y as f32 / SCROLL_LINE_PIXELS
+                    }
+                };
+                let steps = self.scroll.trunc();
+                self.scroll -= steps;
+                self.select_next(steps as i32);
+                true
+            }
             _ => false,
         }
     }
 
-    pub fn update(&mut self, camera: &Camera, projection: &Projection) {
+    fn break_block(&mut self) {
+        if let Some(target) = self.target {
+            self.world.set_block(target.pos, block::AIR);
+        }
+    }
+
+    //against the face the camera looks at
+    fn place_block(&mut self) {
+        let (Some(target), Some(&block)) = (self.target, self.placeable.get(self.selected)) else {
+            return;
+        };
+        let pos = target.pos + target.normal;
+        //a zero normal means the camera is inside of the target
+        if target.normal == cgmath::Vector3::new(0, 0, 0) || self.occupied.contains(&pos) {
+            return;
+        }
+        self.world.set_block(pos, block);
+    }
+
+    //scrolling down selects the next block
+    fn select_next(&mut self, steps: i32) {
+        if self.placeable.is_empty() || steps == 0 {
+            return;
+        }
+        let count = self.placeable.len() as i32;
+        self.selected = (self.selected as i32 - steps).rem_euclid(count) as usize;
+        let name = &self.world.registry().get(self.placeable[self.selected]).name;
+        log::info!("selected {}", name);
+    }
+
+    pub fn save(&mut self) {
+        if let Err(e) = self.world.save() {
+            log::error!("couldn't save the world: {}", e);
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera, player: &Player, projection: &Projection) {
+        self.world.update(camera.camera_pos);
+
+        self.occupied = player.blocks();
+        self.occupied.push(WorldPos::from_point(camera.camera_pos));
+        let target = self.world.raycast(camera.camera_pos, camera.camera_front(), REACH);
+        if let Some(hit) = target {
+            if self.target.map(|t| t.pos) != Some(hit.pos) {
+                let (vertices, _) = outline_mesh(hit.pos);
+                self.queue.write_buffer(
+                    &self.outline_vertex_buffer,
+                    0,
+                    bytemuck::cast_slice(&vertices),
+                );
+            }
+        }
+        self.target = target;
+
         self.camera_uniform.update_view_proj(camera, projection);
         self.queue.write_buffer(
             &self.camera_buffer,
@@ -382,14 +623,18 @@ impl Render {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-        let mut vertex_buffers = Vec::new();
-        let mut index_buffers = Vec::new();
-        let mut indiceses = Vec::new();
-        for chunk in self.chunks.iter_mut() {
-            let (vertex_buffer, index_buffer, indices) = chunk.build(&self.device);
-            vertex_buffers.push(vertex_buffer);
-            index_buffers.push(index_buffer);
-            indiceses.push(indices);
+        //only chunks whose mesh changed get uploaded
+        self.frame_stats = FrameStats::default();
+        for chunk in self.world.chunks_mut() {
+            let bytes = chunk.upload(&self.device, &self.queue);
+            if bytes > 0 {
+                self.frame_stats.bytes_uploaded += bytes;
+                self.frame_stats.chunks_uploaded += 1;
+            }
+        }
+        if self.frame_stats.chunks_uploaded > 0 {
+            self.frame_stats.chunks_loaded = self.world.chunks().count();
+            self.frame_stats.block_memory = self.world.block_memory();
         }
 
         {
@@ -418,13 +663,15 @@ impl Render {
                 }),
             });
 
+            //set before any draw, the light cube needs the camera even on
+            //frames without a single chunk mesh
             render_pass.set_pipeline(&self.pipeline);
-            for i in 0..self.chunks.len() {
-                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-                render_pass.set_bind_group(1, &self.light_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, vertex_buffers[i].slice(..));
-                render_pass.set_index_buffer(index_buffers[i].slice(..), wgpu::IndexFormat::Uint32);
-                render_pass.draw_indexed(0..indiceses[i], 0, 0..1);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.light_bind_group, &[]);
+            for buffers in self.world.chunks().filter_map(|chunk| chunk.buffers()) {
+                render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..buffers.index_count, 0, 0..1);
             }
             render_pass.set_pipeline(&self.light_pipeline);
             render_pass.set_vertex_buffer(0, self.light_object_vertex_buffer.slice(..));
@@ -433,6 +680,17 @@ impl Render {
                 wgpu::IndexFormat::Uint32,
             );
             render_pass.draw_indexed(0..36, 0, 0..1);
+
+            if self.target.is_some() {
+                render_pass.set_pipeline(&self.outline_pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.outline_vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    self.outline_index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                render_pass.draw_indexed(0..24, 0, 0..1);
+            }
         }
         {
             
@@ -444,6 +702,16 @@ impl Render {
     }
 }
 
+fn outline_mesh(pos: WorldPos) -> (Vec<chunk::Vertex>, Vec<u32>) {
+    chunk::edge_mesh(
+        pos.x as f32 - OUTLINE_GROW,
+        pos.y as f32 - OUTLINE_GROW,
+        pos.z as f32 - OUTLINE_GROW,
+        1.0 + 2.0 * OUTLINE_GROW,
+        OUTLINE_COLOR,
+    )
+}
+
 fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -460,7 +728,7 @@ fn create_render_pipeline(
         vertex: wgpu::VertexState {
             module: &shader,
             entry_point: "vs_main",
-            buffers: &vertex_layouts,
+            buffers: vertex_layouts,
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader,
@@ -475,13 +743,16 @@ fn create_render_pipeline(
             })],
         }),
         primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
+            topology: match mode {
+                RenderingMode::Lines => wgpu::PrimitiveTopology::LineList,
+                _ => wgpu::PrimitiveTopology::TriangleList,
+            },
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
             cull_mode: Some(wgpu::Face::Back),
             // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
             polygon_mode: match mode {
-                RenderingMode::Fill => wgpu::PolygonMode::Fill,
+                RenderingMode::Fill | RenderingMode::Lines => wgpu::PolygonMode::Fill,
                 RenderingMode::Wireframe => wgpu::PolygonMode::Line,
             },
             // Requires Features::DEPTH_CLIP_CONTROL
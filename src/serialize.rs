@@ -0,0 +1,267 @@
+use crate::block::BlockId;
+use crate::coords::ChunkSize;
+use crate::palette::PaletteStorage;
+
+//run length encoded voxel data of a single chunk, used for region files
+//and meant for sending chunks over the network. every number is little endian:
+//  magic "VXCK", version u8
+//  chunk width, height and depth as u16
+//  run count u32
+//  runs of (length, block id), both LEB128 varints, in LocalPos::index order
+//  crc32 of everything before it
+const MAGIC: &[u8; 4] = b"VXCK";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 4 + 1 + 3 * 2 + 4;
+const CHECKSUM_SIZE: usize = 4;
+//a run of one block with the largest id, a 1 byte length and a 3 byte id
+const MAX_RUN_SIZE: usize = 1 + 3;
+
+//the most bytes encode can return for a chunk of this size, when every
+//block differs from the one before it
+pub fn max_encoded_len(size: ChunkSize) -> usize {
+    HEADER_SIZE + size.volume() * MAX_RUN_SIZE + CHECKSUM_SIZE
+}
+
+pub fn encode(blocks: &PaletteStorage, size: ChunkSize) -> Vec<u8> {
+    let mut runs: Vec<(u32, BlockId)> = Vec::new();
+    for block in blocks.iter() {
+        match runs.last_mut() {
+            Some((length, last)) if *last == block => *length += 1,
+            _ => runs.push((1, block)),
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + runs.len() * 3 + CHECKSUM_SIZE);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    for dim in [size.width, size.height, size.depth] {
+        bytes.extend_from_slice(&(dim as u16).to_le_bytes());
+    }
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (length, block) in runs {
+        write_varint(&mut bytes, length);
+        write_varint(&mut bytes, block as u32);
+    }
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+//never panics, whatever the input. chunks saved with a different size are an error
+pub fn decode(bytes: &[u8], expected_size: ChunkSize) -> anyhow::Result<PaletteStorage> {
+    if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE {
+        anyhow::bail!("chunk data is truncated ({} bytes)", bytes.len());
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+    if &body[0..4] != MAGIC {
+        anyhow::bail!("not chunk data");
+    }
+    if body[4] != VERSION {
+        anyhow::bail!("chunk data has version {}, expected {}", body[4], VERSION);
+    }
+    let expected = u32::from_le_bytes(checksum.try_into().unwrap());
+    if crc32(body) != expected {
+        anyhow::bail!("chunk data checksum doesn't match");
+    }
+
+    let read_u16 = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]) as usize;
+    let size = ChunkSize::new(read_u16(5), read_u16(7), read_u16(9));
+    if size != expected_size {
+        anyhow::bail!(
+            "chunk data is {}x{}x{}, expected {}x{}x{}",
+            size.width,
+            size.height,
+            size.depth,
+            expected_size.width,
+            expected_size.height,
+            expected_size.depth
+        );
+    }
+    let block_count = size.volume();
+    let run_count = u32::from_le_bytes(body[11..15].try_into().unwrap());
+
+    let mut blocks = Vec::with_capacity(block_count);
+    let mut reader = &body[HEADER_SIZE..];
+    for _ in 0..run_count {
+        let length = read_varint(&mut reader)? as usize;
+        let block = BlockId::try_from(read_varint(&mut reader)?)
+            .map_err(|_| anyhow::anyhow!("chunk data has an invalid block id"))?;
+        if length == 0 || length > block_count - blocks.len() {
+            anyhow::bail!("chunk data has a run of {} blocks that doesn't fit", length);
+        }
+        blocks.resize(blocks.len() + length, block);
+    }
+    if blocks.len() != block_count {
+        anyhow::bail!(
+            "chunk data has {} blocks, expected {}",
+            blocks.len(),
+            block_count
+        );
+    }
+    if !reader.is_empty() {
+        anyhow::bail!("chunk data has {} trailing bytes", reader.len());
+    }
+    Ok(PaletteStorage::from_slice(&blocks))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(reader: &mut &[u8]) -> anyhow::Result<u32> {
+    let mut value: u32 = 0;
+    //a u32 takes at most 5 bytes
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = reader
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("chunk data is truncated"))?;
+        *reader = rest;
+        let bits = (byte & 0x7F) as u32;
+        if shift == 28 && bits > 0x0F {
+            break;
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("chunk data has a number that doesn't fit in 32 bits")
+}
+
+//crc-32 (ieee), bit by bit since chunks are only a few kilobytes
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::AIR;
+    use crate::noise::position_hash;
+
+    const SIZE: ChunkSize = ChunkSize::DEFAULT;
+    const BLOCK_COUNT: usize = SIZE.volume();
+
+    fn layered() -> PaletteStorage {
+        let blocks: Vec<BlockId> = (0..BLOCK_COUNT)
+            .map(|i| match i % SIZE.height {
+                0..=5 => 1,
+                6..=8 => 2,
+                9 => 300,
+                _ => AIR,
+            })
+            .collect();
+        PaletteStorage::from_slice(&blocks)
+    }
+
+    //ids up to 4096, so most of them take two varint bytes
+    fn noisy() -> PaletteStorage {
+        let blocks: Vec<BlockId> = (0..BLOCK_COUNT)
+            .map(|i| (position_hash(99, i as i32, 0, 0) * 4096.0) as BlockId)
+            .collect();
+        PaletteStorage::from_slice(&blocks)
+    }
+
+    #[test]
+    fn round_trip() {
+        for blocks in [PaletteStorage::new(BLOCK_COUNT, AIR), layered(), noisy()] {
+            let decoded = decode(&encode(&blocks, SIZE), SIZE).unwrap();
+            assert_eq!(decoded.to_vec(), blocks.to_vec());
+        }
+    }
+
+    #[test]
+    fn sizes_have_to_match() {
+        let tall = ChunkSize::new(16, 256, 16);
+        let blocks = PaletteStorage::new(tall.volume(), 1);
+        let bytes = encode(&blocks, tall);
+        assert_eq!(decode(&bytes, tall).unwrap().to_vec(), blocks.to_vec());
+        assert!(decode(&bytes, SIZE).is_err());
+    }
+
+    #[test]
+    fn uniform_chunks_are_tiny() {
+        let bytes = encode(&PaletteStorage::new(BLOCK_COUNT, 1), SIZE);
+        assert!(bytes.len() < 32, "{} bytes", bytes.len());
+    }
+
+    #[test]
+    fn worst_case_fits_the_limit() {
+        let blocks: Vec<BlockId> = (0..BLOCK_COUNT)
+            .map(|i| BlockId::MAX - (i % 2) as BlockId)
+            .collect();
+        let bytes = encode(&PaletteStorage::from_slice(&blocks), SIZE);
+        assert_eq!(bytes.len(), max_encoded_len(SIZE));
+    }
+
+    #[test]
+    fn known_checksum() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let bytes = encode(&layered(), SIZE);
+        for length in 0..bytes.len() {
+            assert!(decode(&bytes[..length], SIZE).is_err(), "{} bytes", length);
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(decode(&longer, SIZE).is_err());
+    }
+
+    #[test]
+    fn corrupted_data_is_an_error() {
+        let bytes = encode(&layered(), SIZE);
+        //a different bit in every byte, all of them would make the test slow
+        for i in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 1 << (i % 8);
+            assert!(decode(&corrupted, SIZE).is_err(), "byte {}", i);
+        }
+    }
+
+    //structurally broken data with a valid checksum, like a buggy encoder would write
+    #[test]
+    fn invalid_runs_are_an_error() {
+        let with_runs = |runs: &[(u32, u32)], version: u8| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.push(version);
+            for dim in [SIZE.width, SIZE.height, SIZE.depth] {
+                bytes.extend_from_slice(&(dim as u16).to_le_bytes());
+            }
+            bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+            for &(length, block) in runs {
+                write_varint(&mut bytes, length);
+                write_varint(&mut bytes, block);
+            }
+            let checksum = crc32(&bytes);
+            bytes.extend_from_slice(&checksum.to_le_bytes());
+            bytes
+        };
+        let all = BLOCK_COUNT as u32;
+
+        assert!(decode(&with_runs(&[(all, 1)], VERSION), SIZE).is_ok());
+        assert!(decode(&with_runs(&[(all, 1)], VERSION + 1), SIZE).is_err());
+        assert!(decode(&with_runs(&[(all - 1, 1)], VERSION), SIZE).is_err());
+        assert!(decode(&with_runs(&[(all, 1), (1, 1)], VERSION), SIZE).is_err());
+        assert!(decode(&with_runs(&[(0, 1), (all, 1)], VERSION), SIZE).is_err());
+        assert!(decode(&with_runs(&[(u32::MAX, 1)], VERSION), SIZE).is_err());
+        assert!(decode(&with_runs(&[(all, 70000)], VERSION), SIZE).is_err());
+    }
+}
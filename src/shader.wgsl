@@ -16,6 +16,7 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) emissive: f32,
 }
 
 struct VertexOutput {
@@ -23,6 +24,7 @@ struct VertexOutput {
     @location(0) color: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) world_pos: vec3<f32>,
+    @location(3) emissive: f32,
 }
 
 @vertex
@@ -33,6 +35,7 @@ fn vs_main(
     out.color = model.color;
     out.normal = model.normal;
     out.world_pos = model.position;
+    out.emissive = model.emissive;
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -52,6 +55,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let specular_strength = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0);
     let specular = specular_strength * light.color;
     //adding everything together
-    let color = in.color * (ambient + diffuse + specular);
+    let lit = in.color * (ambient + diffuse + specular);
+    //emissive blocks keep their own color no matter the light
+    let color = mix(lit, in.color, in.emissive);
     return vec4<f32>(color, 1.0);
 }
\ No newline at end of file
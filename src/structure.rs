@@ -0,0 +1,451 @@
+use crate::biome::Biome;
+use crate::block::{BlockId, BlockRegistry, AIR};
+use crate::chunk::ChunkMeshData;
+use crate::noise;
+
+pub const BUILTIN_STRUCTURES: &str = include_str!("../res/structures.txt");
+
+//a small voxel template, see res/structures.txt for the format
+#[derive(Clone, Debug)]
+pub struct Structure {
+    pub name: String,
+    //empty means every biome
+    pub biomes: Vec<Biome>,
+    pub spacing: i32,
+    pub chance: f64,
+    //placed exactly once centered on this column
+    pub at: Option<(i32, i32)>,
+    pub sink: i32,
+    size: [usize; 3],
+    //x + z * width + y * width * depth, None leaves the block untouched
+    blocks: Vec<Option<BlockId>>,
+}
+
+impl Structure {
+    pub fn spawns_in(&self, biome: Biome) -> bool {
+        self.biomes.is_empty() || self.biomes.contains(&biome)
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<BlockId> {
+        let [width, _, depth] = self.size;
+        self.blocks[x + z * width + y * width * depth]
+    }
+}
+
+pub struct StructureSet {
+    structures: Vec<Structure>,
+}
+
+impl StructureSet {
+    //the templates compiled into the binary
+    pub fn builtin(registry: &BlockRegistry) -> Self {
+        Self::parse(BUILTIN_STRUCTURES, registry).expect("builtin res/structures.txt is invalid")
+    }
+
+    pub fn load(
+        path: impl AsRef<std::path::Path>,
+        registry: &BlockRegistry,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("couldn't read {}: {}", path.display(), e))?;
+        Self::parse(&source, registry).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn load_or_builtin(path: impl AsRef<std::path::Path>, registry: &BlockRegistry) -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let _ = path;
+                Self::builtin(registry)
+            } else {
+                match Self::load(path, registry) {
+                    Ok(structures) => structures,
+                    Err(e) => {
+                        log::warn!("falling back to builtin structures: {}", e);
+                        Self::builtin(registry)
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn parse(source: &str, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let mut structures = Vec::new();
+        let mut current: Option<StructureBuilder> = None;
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if let Some(builder) = current.take() {
+                    structures.push(builder.build()?);
+                }
+                current = Some(StructureBuilder::new(name.trim()));
+                continue;
+            }
+
+            let builder = current.as_mut().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "line {}: `{}` outside of a [structure] section",
+                    line_number,
+                    line
+                )
+            })?;
+
+            if line == "layer" {
+                builder.layers.push(Vec::new());
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) else {
+                let layer = builder.layers.last_mut().ok_or_else(|| {
+                    anyhow::anyhow!("line {}: row before the first `layer`", line_number)
+                })?;
+                layer.push(line.chars().collect());
+                continue;
+            };
+
+            let number_error =
+                || anyhow::anyhow!("line {}: invalid {} `{}`", line_number, key, value);
+            match key {
+                "biomes" if value == "any" => builder.structure.biomes.clear(),
+                "biomes" => {
+                    builder.structure.biomes = value
+                        .split_whitespace()
+                        .map(|name| {
+                            Biome::from_name(name).ok_or_else(|| {
+                                anyhow::anyhow!("line {}: unknown biome `{}`", line_number, name)
+                            })
+                        })
+                        .collect::<anyhow::Result<_>>()?
+                }
+                "spacing" => {
+                    builder.structure.spacing = value
+                        .parse()
+                        .ok()
+                        .filter(|&spacing| spacing > 0)
+                        .ok_or_else(number_error)?
+                }
+                "chance" => builder.structure.chance = value.parse().map_err(|_| number_error())?,
+                "sink" => builder.structure.sink = value.parse().map_err(|_| number_error())?,
+                "at" => {
+                    let coordinates = value
+                        .split_whitespace()
+                        .map(|c| c.parse::<i32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| number_error())?;
+                    match coordinates[..] {
+                        [x, z] => builder.structure.at = Some((x, z)),
+                        _ => return Err(number_error()),
+                    }
+                }
+                _ => {
+                    let mut chars = key.chars();
+                    let (Some(c), None) = (chars.next(), chars.next()) else {
+                        anyhow::bail!("line {}: unknown key `{}`", line_number, key);
+                    };
+                    if c == '.' || c == '_' {
+                        anyhow::bail!("line {}: `{}` can't be redefined", line_number, c);
+                    }
+                    let block = registry.id(value).ok_or_else(|| {
+                        anyhow::anyhow!("line {}: unknown block `{}`", line_number, value)
+                    })?;
+                    builder.legend.push((c, block));
+                }
+            }
+        }
+
+        if let Some(builder) = current {
+            structures.push(builder.build()?);
+        }
+        Ok(Self { structures })
+    }
+
+    //stamps every structure that overlaps the chunk, including the ones
+    //anchored in neighbouring chunks, so a structure crossing a border is
+    //completed when the other chunk generates. surface returns the height
+    //of the column a structure would stand on, or None if it can't go there
+    pub fn place(
+        &self,
+        seed: u64,
+        chunk: &mut ChunkMeshData,
+        surface: impl Fn(&Structure, i32, i32) -> Option<i32>,
+    ) {
+        let origin = chunk.origin();
+        let size = chunk.size();
+        let (chunk_width, chunk_height, chunk_depth) =
+            (size.width as i32, size.height as i32, size.depth as i32);
+
+        for (index, structure) in self.structures.iter().enumerate() {
+            let [width, height, depth] = structure.size.map(|s| s as i32);
+            //columns whose structure would reach into the chunk
+            let (min_x, max_x) = (origin.x - width, origin.x + chunk_width + width);
+            let (min_z, max_z) = (origin.z - depth, origin.z + chunk_depth + depth);
+
+            let mut anchors = Vec::new();
+            if let Some((x, z)) = structure.at {
+                anchors.push((x, z));
+            } else {
+                let salt = seed ^ (index as u64 + 1).wrapping_mul(0x2545_F491_4F6C_DD1D);
+                let spacing = structure.spacing;
+                for cell_z in min_z.div_euclid(spacing)..=max_z.div_euclid(spacing) {
+                    for cell_x in min_x.div_euclid(spacing)..=max_x.div_euclid(spacing) {
+                        let hash = |i| noise::position_hash(salt, cell_x, i, cell_z);
+                        if hash(0) >= structure.chance {
+                            continue;
+                        }
+                        anchors.push((
+                            cell_x * spacing + (hash(1) * spacing as f64) as i32,
+                            cell_z * spacing + (hash(2) * spacing as f64) as i32,
+                        ));
+                    }
+                }
+            }
+
+            for (anchor_x, anchor_z) in anchors {
+                let corner_x = anchor_x - width / 2;
+                let corner_z = anchor_z - depth / 2;
+                if corner_x + width <= origin.x
+                    || corner_x >= origin.x + chunk_width
+                    || corner_z + depth <= origin.z
+                    || corner_z >= origin.z + chunk_depth
+                {
+                    continue;
+                }
+                let Some(ground) = surface(structure, anchor_x, anchor_z) else {
+                    continue;
+                };
+                let corner_y = ground + 1 - structure.sink;
+                if corner_y + height <= origin.y || corner_y >= origin.y + chunk_height {
+                    continue;
+                }
+                stamp(structure, chunk, [corner_x, corner_y, corner_z]);
+            }
+        }
+    }
+}
+
+//writes the part of the structure with its minimum corner at corner
+//that falls inside the chunk
+fn stamp(structure: &Structure, chunk: &mut ChunkMeshData, corner: [i32; 3]) {
+    let origin = chunk.origin();
+    let [width, height, depth] = structure.size;
+    for y in 0..height {
+        for z in 0..depth {
+            for x in 0..width {
+                let Some(block) = structure.get(x, y, z) else {
+                    continue;
+                };
+                let (local_x, local_y, local_z) = (
+                    corner[0] + x as i32 - origin.x,
+                    corner[1] + y as i32 - origin.y,
+                    corner[2] + z as i32 - origin.z,
+                );
+                if chunk.size().contains(local_x, local_y, local_z) {
+                    chunk.set_block(local_x as usize, local_y as usize, local_z as usize, block);
+                }
+            }
+        }
+    }
+}
+
+struct StructureBuilder {
+    structure: Structure,
+    legend: Vec<(char, BlockId)>,
+    //layers of rows of characters
+    layers: Vec<Vec<Vec<char>>>,
+}
+
+impl StructureBuilder {
+    fn new(name: &str) -> Self {
+        Self {
+            structure: Structure {
+                name: name.to_string(),
+                biomes: Vec::new(),
+                spacing: 16,
+                chance: 0.0,
+                at: None,
+                sink: 0,
+                size: [0; 3],
+                blocks: Vec::new(),
+            },
+            legend: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    fn build(mut self) -> anyhow::Result<Structure> {
+        let name = &self.structure.name;
+        let height = self.layers.len();
+        let depth = self.layers.first().map_or(0, |layer| layer.len());
+        let width = self
+            .layers
+            .first()
+            .and_then(|layer| layer.first())
+            .map_or(0, |row| row.len());
+        if width == 0 || depth == 0 {
+            anyhow::bail!("structure [{}] has no layers", name);
+        }
+
+        let mut blocks = Vec::with_capacity(width * depth * height);
+        for (y, layer) in self.layers.iter().enumerate() {
+            if layer.len() != depth || layer.iter().any(|row| row.len() != width) {
+                anyhow::bail!(
+                    "structure [{}]: layer {} isn't {}x{} like the first one",
+                    name,
+                    y + 1,
+                    width,
+                    depth
+                );
+            }
+            for row in layer {
+                for &c in row {
+                    blocks.push(match c {
+                        '.' => None,
+                        '_' => Some(AIR),
+                        _ => Some(
+                            self.legend
+                                .iter()
+                                .find(|(key, _)| *key == c)
+                                .map(|&(_, block)| block)
+                                .ok_or_else(|| {
+                                    anyhow::anyhow!("structure [{}]: unknown block `{}`", name, c)
+                                })?,
+                        ),
+                    });
+                }
+            }
+        }
+
+        self.structure.size = [width, height, depth];
+        self.structure.blocks = blocks;
+        Ok(self.structure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::{ChunkPos, ChunkSize};
+
+    const PILLAR: &str = "
+        [pillar]
+        at = 16 3
+        s = stone
+        layer
+        sss
+        s.s
+        sss
+        layer
+        ___
+        _s_
+        ___
+    ";
+
+    fn parse(source: &str) -> anyhow::Result<StructureSet> {
+        StructureSet::parse(source, &BlockRegistry::builtin())
+    }
+
+    #[test]
+    fn parses_layers_bottom_first() {
+        let structures = parse(PILLAR).unwrap();
+        let pillar = &structures.structures[0];
+        let stone = BlockRegistry::builtin().id("stone");
+        assert_eq!(pillar.size, [3, 2, 3]);
+        assert_eq!(pillar.get(0, 0, 0), stone);
+        assert_eq!(pillar.get(1, 0, 1), None);
+        assert_eq!(pillar.get(0, 1, 0), Some(AIR));
+        assert_eq!(pillar.get(1, 1, 1), stone);
+    }
+
+    #[test]
+    fn rejects_broken_templates() {
+        assert!(parse("[a]\nlayer\nxx").is_err());
+        assert!(parse("[a]\nx = stone\nlayer\nxx\nx").is_err());
+        assert!(parse("[a]\nx = bedrock\nlayer\nx").is_err());
+        assert!(parse("[a]\nbiomes = swamp\nlayer\n.").is_err());
+        assert!(parse("[a]\nlayer\n.").is_ok());
+    }
+
+    #[test]
+    fn structures_crossing_borders_are_completed() {
+        //the pillar is centered on x = 16 z = 3 so it spans x 15..=17 z 2..=4
+        let structures = parse(PILLAR).unwrap();
+        let stone = BlockRegistry::builtin().id("stone").unwrap();
+        let mut left = ChunkMeshData::new(ChunkPos::new(0, 0, 0), ChunkSize::DEFAULT);
+        let mut right = ChunkMeshData::new(ChunkPos::new(1, 0, 0), ChunkSize::DEFAULT);
+        structures.place(1, &mut right, |_, _, _| Some(4));
+        structures.place(1, &mut left, |_, _, _| Some(4));
+
+        //bottom layer sits on top of the surface at y 5
+        assert_eq!(left.get_block(15, 5, 3), stone);
+        assert_eq!(right.get_block(0, 5, 2), stone);
+        assert_eq!(right.get_block(0, 5, 3), AIR);
+        assert_eq!(right.get_block(1, 5, 4), stone);
+        assert_eq!(right.get_block(0, 6, 3), stone);
+        assert_eq!(left.get_block(15, 6, 3), AIR);
+        assert_eq!(right.get_block(2, 5, 3), AIR);
+    }
+
+    #[test]
+    fn spaced_structures_are_the_same_in_any_chunk() {
+        let structures = parse(
+            "
+            [bush]
+            spacing = 8
+            chance = 0.7
+            l = leaves
+            layer
+            lllll
+            lllll
+            lllll
+            layer
+            .....
+            .lll.
+            .....
+            ",
+        )
+        .unwrap();
+        //uneven ground so the height comes from the anchor column
+        let surface = |_: &Structure, x: i32, z: i32| Some(3 + (x + 2 * z).rem_euclid(4));
+        let size = ChunkSize::DEFAULT;
+        let wide = ChunkSize::new(32, 16, 16);
+
+        let mut straddling = 0;
+        for seed in 0..8 {
+            let mut left = ChunkMeshData::new(ChunkPos::new(0, 0, 0), size);
+            let mut right = ChunkMeshData::new(ChunkPos::new(1, 0, 0), size);
+            let mut both = ChunkMeshData::new(ChunkPos::new(0, 0, 0), wide);
+            //the right chunk first, like a neighbour that streams in early
+            structures.place(seed, &mut right, surface);
+            structures.place(seed, &mut left, surface);
+            structures.place(seed, &mut both, surface);
+
+            for z in 0..16 {
+                for y in 0..16 {
+                    for x in 0..32 {
+                        let (chunk, local_x) = if x < 16 { (&left, x) } else { (&right, x - 16) };
+                        assert_eq!(
+                            chunk.get_block(local_x, y, z),
+                            both.get_block(x, y, z),
+                            "seed {} at {} {} {}",
+                            seed,
+                            x,
+                            y,
+                            z
+                        );
+                    }
+                    //bushes reaching across the border
+                    if left.get_block(15, y, z) != AIR && right.get_block(0, y, z) != AIR {
+                        straddling += 1;
+                    }
+                }
+            }
+        }
+        assert!(straddling > 0);
+    }
+}
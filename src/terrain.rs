@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use crate::biome::{Biome, BiomeMap};
+use crate::block::{BlockId, BlockRegistry, AIR};
+use crate::cave::CaveCarver;
+use crate::chunk::ChunkMeshData;
+use crate::noise::{self, Perlin};
+use crate::structure::{StructureSet, BUILTIN_STRUCTURES};
+
+//fills a freshly created (all air) chunk, gets called from the worker
+//threads so it has to be deterministic for a given chunk position
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, chunk: &mut ChunkMeshData);
+}
+
+//block ids resolved from the biome's block names
+struct BiomeBlocks {
+    surface: BlockId,
+    filler: BlockId,
+    decoration: Option<(BlockId, f64, i32)>,
+}
+
+pub struct NoiseTerrain {
+    seed: u64,
+    noise: Perlin,
+    pub biomes: BiomeMap,
+    pub caves: CaveCarver,
+    pub structures: StructureSet,
+    //in blocks, bigger means wider hills
+    pub scale: f64,
+    pub octaves: u32,
+    pub dirt_depth: i32,
+    //everything below it that isn't terrain is water
+    pub sea_level: i32,
+
+    stone: BlockId,
+    water: BlockId,
+    biome_blocks: HashMap<Biome, BiomeBlocks>,
+}
+
+impl NoiseTerrain {
+    //fails when the registry lacks a block the terrain is built from,
+    //decorations whose block is missing are left out
+    pub fn new(seed: u64, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let block = |name| {
+            registry
+                .id(name)
+                .ok_or_else(|| anyhow::anyhow!("the terrain needs a `{}` block", name))
+        };
+        let mut biome_blocks = HashMap::new();
+        for biome in Biome::ALL {
+            let params = biome.params();
+            let decoration = params.decoration.and_then(|d| match registry.id(d.block) {
+                Some(id) => Some((id, d.chance, d.height)),
+                None => {
+                    log::warn!("no `{}` block, {:?} won't be decorated", d.block, biome);
+                    None
+                }
+            });
+            let blocks = BiomeBlocks {
+                surface: block(params.surface)?,
+                filler: block(params.filler)?,
+                decoration,
+            };
+            biome_blocks.insert(biome, blocks);
+        }
+
+        Ok(Self {
+            seed,
+            noise: Perlin::new(seed),
+            biomes: BiomeMap::new(seed),
+            caves: CaveCarver::new(seed),
+            structures: StructureSet::parse(BUILTIN_STRUCTURES, registry)?,
+            scale: 48.0,
+            octaves: 4,
+            dirt_depth: 3,
+            sea_level: 5,
+
+            stone: block("stone")?,
+            water: block("water")?,
+            biome_blocks,
+        })
+    }
+
+    //world space height of the top block of the column
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let (base_height, amplitude) = self.biomes.blended_height_params(x, z);
+        let noise = self
+            .noise
+            .fbm_2d(x as f64 / self.scale, z as f64 / self.scale, self.octaves);
+        (base_height + noise * amplitude).floor() as i32
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn generate(&self, chunk: &mut ChunkMeshData) {
+        let origin = chunk.origin();
+        let size = chunk.size();
+        //surface height and the highest block caves may carve, per column
+        let mut columns = Vec::with_capacity(size.width * size.depth);
+        for z in 0..size.depth {
+            for x in 0..size.width {
+                let (world_x, world_z) = (origin.x + x as i32, origin.z + z as i32);
+                let height = self.height_at(world_x, world_z);
+                let blocks = &self.biome_blocks[&self.biomes.biome_at(world_x, world_z)];
+
+                //decorations only grow on dry land
+                let decoration = match blocks.decoration {
+                    Some((block, chance, decoration_height))
+                        if height >= self.sea_level
+                            && noise::position_hash(self.seed, world_x, height, world_z)
+                                < chance =>
+                    {
+                        Some((block, height + decoration_height))
+                    }
+                    _ => None,
+                };
+                //the block a decoration stands on has to stay
+                let cave_top = if decoration.is_some() {
+                    height - 1
+                } else {
+                    height
+                };
+                columns.push((height, cave_top));
+
+                for y in 0..size.height {
+                    let world_y = origin.y + y as i32;
+                    let block = if world_y > height {
+                        match decoration {
+                            Some((block, top)) if world_y <= top => block,
+                            _ if world_y <= self.sea_level => self.water,
+                            _ => AIR,
+                        }
+                    } else if world_y == height {
+                        blocks.surface
+                    } else if world_y > height - self.dirt_depth {
+                        blocks.filler
+                    } else {
+                        self.stone
+                    };
+                    if block != AIR {
+                        chunk.set_block(x, y, z, block);
+                    }
+                }
+            }
+        }
+
+        //caves under the sea would leave air pockets the water never fills,
+        //and carving under decorations would leave them floating
+        self.caves.carve(chunk, |x, y, z, block| {
+            let (height, cave_top) = columns[z * size.width + x];
+            block != self.water && height >= self.sea_level && origin.y + (y as i32) <= cave_top
+        });
+
+        self.structures.place(self.seed, chunk, |structure, x, z| {
+            let height = self.height_at(x, z);
+            //fixed structures like the spawn platform float on the sea
+            if structure.at.is_some() {
+                return Some(height.max(self.sea_level));
+            }
+            let dry = height >= self.sea_level;
+            (dry && structure.spawns_in(self.biomes.biome_at(x, z))).then_some(height)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::{ChunkPos, ChunkSize, LocalPos};
+
+    fn generate(terrain: &NoiseTerrain, position: ChunkPos) -> ChunkMeshData {
+        let mut chunk = ChunkMeshData::new(position, ChunkSize::DEFAULT);
+        terrain.generate(&mut chunk);
+        chunk
+    }
+
+    fn blocks(chunk: &ChunkMeshData) -> Vec<BlockId> {
+        let size = chunk.size();
+        (0..size.volume())
+            .map(|index| {
+                let local = LocalPos::from_index(index, size);
+                chunk.get_block(local.x, local.y, local.z)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_seed_decides_the_world() {
+        let registry = BlockRegistry::builtin();
+        let terrain = NoiseTerrain::new(3, &registry).unwrap();
+        //far away from the origin and in negative coordinates too
+        for position in [ChunkPos::new(0, 0, 0), ChunkPos::new(-4000, 0, 9000)] {
+            let first = blocks(&generate(&terrain, position));
+            //a second generator with the same seed, like after a restart
+            let again = NoiseTerrain::new(3, &registry).unwrap();
+            assert_eq!(first, blocks(&generate(&again, position)));
+            assert_eq!(first, blocks(&generate(&terrain, position)));
+        }
+
+        let other = NoiseTerrain::new(4, &registry).unwrap();
+        let differs = (0..8).any(|x| {
+            let position = ChunkPos::new(x, 0, 0);
+            blocks(&generate(&terrain, position)) != blocks(&generate(&other, position))
+        });
+        assert!(differs);
+        assert!((0..64).any(|x| terrain.height_at(x, 0) != other.height_at(x, 0)));
+    }
+
+    #[test]
+    fn neighbouring_columns_are_continuous() {
+        let terrain = NoiseTerrain::new(7, &BlockRegistry::builtin()).unwrap();
+        //crosses chunk borders and the origin on both axes
+        for z in -40..40 {
+            for x in -40..40 {
+                let height = terrain.height_at(x, z);
+                for (dx, dz) in [(1, 0), (0, 1)] {
+                    let step = (terrain.height_at(x + dx, z + dz) - height).abs();
+                    assert!(step <= 2, "{} {}: {}", x, z, step);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn caves_dont_leave_decorations_floating() {
+        let registry = BlockRegistry::builtin();
+        let terrain = NoiseTerrain::new(2, &registry).unwrap();
+        let decorations: Vec<BlockId> = ["flower", "cactus"]
+            .iter()
+            .map(|name| registry.id(name).unwrap())
+            .collect();
+        let mut checked = 0;
+        for cz in -6..6 {
+            for cx in -6..6 {
+                let chunk = generate(&terrain, ChunkPos::new(cx, 0, cz));
+                let size = chunk.size();
+                for index in 0..size.volume() {
+                    let local = LocalPos::from_index(index, size);
+                    let block = chunk.get_block(local.x, local.y, local.z);
+                    if local.y == 0 || !decorations.contains(&block) {
+                        continue;
+                    }
+                    checked += 1;
+                    assert_ne!(chunk.get_block(local.x, local.y - 1, local.z), AIR);
+                }
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn missing_blocks_dont_crash() {
+        let registry = BlockRegistry::builtin();
+        let without = |removed: &[&str]| {
+            let source = include_str!("../res/blocks.txt")
+                .split("\n[")
+                .filter(|section| {
+                    !removed
+                        .iter()
+                        .any(|r| section.starts_with(&format!("{}]", r)))
+                })
+                .collect::<Vec<_>>()
+                .join("\n[");
+            BlockRegistry::parse(&source).unwrap()
+        };
+
+        //decorations are skipped, the rest of the world stays the same
+        let undecorated = without(&["flower", "cactus"]);
+        let terrain = NoiseTerrain::new(5, &undecorated).unwrap();
+        let full = NoiseTerrain::new(5, &registry).unwrap();
+        assert!(terrain.biome_blocks[&Biome::Plains].decoration.is_none());
+        assert!(terrain.biome_blocks[&Biome::Desert].decoration.is_none());
+        assert_eq!(terrain.height_at(100, -30), full.height_at(100, -30));
+
+        for missing in ["stone", "water", "snow"] {
+            let error = NoiseTerrain::new(5, &without(&[missing])).err().unwrap();
+            assert!(error.to_string().contains(missing), "{}", error);
+        }
+    }
+
+    #[test]
+    fn chunk_size_doesnt_change_the_world() {
+        let terrain = NoiseTerrain::new(11, &BlockRegistry::builtin()).unwrap();
+        let big = ChunkSize::new(32, 32, 32);
+        let mut big_chunk = ChunkMeshData::new(ChunkPos::new(-1, 0, 0), big);
+        terrain.generate(&mut big_chunk);
+
+        //the same blocks as 8 default sized chunks
+        let small = ChunkSize::DEFAULT;
+        for cz in 0..2 {
+            for cy in 0..2 {
+                for cx in -2..0 {
+                    let mut chunk = ChunkMeshData::new(ChunkPos::new(cx, cy, cz), small);
+                    terrain.generate(&mut chunk);
+                    for index in 0..small.volume() {
+                        let local = LocalPos::from_index(index, small);
+                        let world = chunk.position().world_pos(local, small);
+                        let (_, big_local) = world.to_chunk(big);
+                        assert_eq!(
+                            chunk.get_block(local.x, local.y, local.z),
+                            big_chunk.get_block(big_local.x, big_local.y, big_local.z),
+                            "{:?}",
+                            world
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
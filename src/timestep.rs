@@ -0,0 +1,85 @@
+use instant::Duration;
+
+//the simulation always steps by this much, no matter the frame rate
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+//after a stall only this many ticks are caught up, the rest of the
+//time is dropped so a slow frame can't cause an even slower one
+pub const MAX_TICKS_PER_FRAME: u32 = 5;
+
+//turns frame times into a number of fixed ticks, what's left over is
+//carried to the next frame
+pub struct FixedTimestep {
+    tick: Duration,
+    max_ticks: u32,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(tick: Duration, max_ticks: u32) -> Self {
+        Self {
+            tick,
+            max_ticks,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn tick(&self) -> Duration {
+        self.tick
+    }
+
+    //how many ticks to simulate for a frame that took frame_time
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+        let mut ticks = 0;
+        while self.accumulator >= self.tick {
+            if ticks == self.max_ticks {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    //how far the time is between the last two ticks, 0..1. rendering
+    //blends the states of those ticks with it
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_add_up_to_ticks() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10), 5);
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-5);
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 0);
+        //the leftover time of earlier frames counts
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-5);
+        assert_eq!(timestep.advance(Duration::from_millis(25)), 2);
+        assert!((timestep.alpha() - 0.7).abs() < 1e-5);
+
+        //the same time in different frames gives the same number of ticks
+        let mut ticks = 0;
+        let mut timestep = FixedTimestep::new(TICK, MAX_TICKS_PER_FRAME);
+        for frame in [7, 3, 30, 1, 12, 17, 30] {
+            ticks += timestep.advance(Duration::from_millis(frame));
+        }
+        assert_eq!(ticks, 6);
+    }
+
+    #[test]
+    fn stalls_only_catch_up_a_few_ticks() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10), 5);
+        assert_eq!(timestep.advance(Duration::from_secs(3)), 5);
+        //the rest of the stall is gone
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 1);
+    }
+}
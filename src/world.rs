@@ -0,0 +1,773 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use cgmath::Vector3;
+
+use crate::block::{BlockId, BlockRegistry, AIR};
+use crate::chunk::{self, ChunkMeshData, MeshOptions, MeshingMode};
+use crate::coords::{ChunkPos, ChunkSize, LocalPos, WorldPos};
+use crate::edit::{BlockChange, EditHistory};
+use crate::jobs::{CancelToken, JobPool};
+use crate::raycast::{self, RayHit};
+use crate::region::{RegionStorage, REGION_SIZE};
+use crate::terrain::TerrainGenerator;
+
+pub struct WorldConfig {
+    //in chunks, measured on the xz plane from the chunk the camera is in
+    pub render_distance: i32,
+    //in chunks, how many layers above and below the camera's chunk are loaded
+    pub vertical_render_distance: i32,
+    //how many generation and how many meshing jobs get queued in a single frame
+    pub chunks_per_frame: usize,
+    pub meshing_mode: MeshingMode,
+    pub seed: u64,
+    //every chunk of the world has this size, changing it needs a new save_dir
+    pub chunk_size: ChunkSize,
+    //darkens faces a bit based on their position so flat areas aren't a single color
+    pub color_variation: bool,
+    //modified chunks are saved to region files in here, None keeps them in memory only
+    pub save_dir: Option<PathBuf>,
+}
+
+enum JobOutput {
+    Generated {
+        coords: ChunkPos,
+        job_id: u64,
+        chunk: Box<ChunkMeshData>,
+    },
+    Meshed {
+        coords: ChunkPos,
+        job_id: u64,
+        vertices: Vec<chunk::Vertex>,
+        indices: Vec<u32>,
+    },
+    Flushed,
+}
+
+struct PendingJob {
+    id: u64,
+    token: CancelToken,
+}
+
+pub struct World {
+    chunks: HashMap<ChunkPos, ChunkMeshData>,
+    registry: Arc<BlockRegistry>,
+    config: WorldConfig,
+    //chunks whose mesh is missing or out of date
+    needs_mesh: HashSet<ChunkPos>,
+    generator: Arc<dyn TerrainGenerator>,
+    //shared with the workers, chunks are loaded from it before falling back to the generator
+    storage: Option<Arc<RegionStorage>>,
+    //chunks that changed since they were generated or loaded
+    modified: HashSet<ChunkPos>,
+    //chunks touched by set_block, meshed on the main thread in the next
+    //update so edits show up right away instead of waiting for a worker
+    edited: HashSet<ChunkPos>,
+    history: EditHistory,
+
+    jobs: JobPool<JobOutput>,
+    next_job_id: u64,
+    //at most one job of each kind is in flight for a chunk, results
+    //that don't match the pending job id were cancelled
+    generating: HashMap<ChunkPos, PendingJob>,
+    meshing: HashMap<ChunkPos, PendingJob>,
+}
+
+const NEIGHBOURS: [Vector3<i32>; 6] = [
+    Vector3::new(1, 0, 0),
+    Vector3::new(-1, 0, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(0, -1, 0),
+    Vector3::new(0, 0, 1),
+    Vector3::new(0, 0, -1),
+];
+
+const EDIT_HISTORY_LIMIT: usize = 1024;
+
+//on the xz plane, the vertical range is checked separately
+fn distance_squared(a: ChunkPos, b: ChunkPos) -> i32 {
+    let (dx, dz) = (a.x - b.x, a.z - b.z);
+    dx * dx + dz * dz
+}
+
+//loading order, chunks at the camera's height come first
+fn load_priority(coords: ChunkPos, center: ChunkPos) -> i32 {
+    let dy = coords.y - center.y;
+    distance_squared(coords, center) + dy * dy
+}
+
+impl World {
+    pub fn new(
+        registry: BlockRegistry,
+        config: WorldConfig,
+        generator: Arc<dyn TerrainGenerator>,
+    ) -> Self {
+        let storage = config
+            .save_dir
+            .clone()
+            .map(|dir| Arc::new(RegionStorage::new(dir, config.chunk_size)));
+        Self {
+            chunks: HashMap::new(),
+            registry: Arc::new(registry),
+            config,
+            needs_mesh: HashSet::new(),
+            generator,
+            storage,
+            modified: HashSet::new(),
+            edited: HashSet::new(),
+            history: EditHistory::new(EDIT_HISTORY_LIMIT),
+
+            jobs: JobPool::new(),
+            next_job_id: 0,
+            generating: HashMap::new(),
+            meshing: HashMap::new(),
+        }
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &ChunkMeshData> {
+        self.chunks.values()
+    }
+
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = &mut ChunkMeshData> {
+        self.chunks.values_mut()
+    }
+
+    //bytes used by the block data of every loaded chunk, meshes not included
+    pub fn block_memory(&self) -> usize {
+        self.chunks
+            .values()
+            .map(|chunk| chunk.chunk_data.memory_usage())
+            .sum()
+    }
+
+    //every loaded chunk gets re-meshed over the next frames
+    pub fn set_meshing_mode(&mut self, mode: MeshingMode) {
+        if self.config.meshing_mode != mode {
+            self.config.meshing_mode = mode;
+            self.needs_mesh.extend(self.chunks.keys().copied());
+        }
+    }
+
+    //blocks in chunks that aren't loaded are air
+    pub fn get_block(&self, pos: WorldPos) -> BlockId {
+        get_block(&self.chunks, self.config.chunk_size, pos)
+    }
+
+    //whether bodies collide with the block. unloaded chunks are solid so
+    //nothing falls out of the world while they load
+    pub fn is_solid(&self, pos: WorldPos) -> bool {
+        let (chunk, local) = pos.to_chunk(self.config.chunk_size);
+        match self.chunks.get(&chunk) {
+            Some(chunk) => {
+                let block = chunk.get_block(local.x, local.y, local.z);
+                self.registry.get(block).solid
+            }
+            None => true,
+        }
+    }
+
+    //first block along the ray, unloaded chunks are see-through
+    pub fn raycast(
+        &self,
+        origin: cgmath::Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        raycast::raycast(origin, direction, max_distance, |pos| self.get_block(pos))
+    }
+
+    //returns false when the chunk isn't loaded. the change can be undone
+    pub fn set_block(&mut self, pos: WorldPos, block: BlockId) -> bool {
+        match self.replace_block(pos, block) {
+            Some(before) => {
+                self.history.push(BlockChange {
+                    pos,
+                    before,
+                    after: block,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    //reverts the last edit, false if there is nothing to undo. edits in
+    //chunks that were unloaded since are skipped
+    pub fn undo(&mut self) -> bool {
+        let Some(change) = self.history.undo() else {
+            return false;
+        };
+        self.replace_block(change.pos, change.before);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(change) = self.history.redo() else {
+            return false;
+        };
+        self.replace_block(change.pos, change.after);
+        true
+    }
+
+    //returns the block that was there before, None if the chunk isn't loaded
+    fn replace_block(&mut self, pos: WorldPos, block: BlockId) -> Option<BlockId> {
+        let size = self.config.chunk_size;
+        let (coords, local) = pos.to_chunk(size);
+        let chunk = self.chunks.get_mut(&coords)?;
+        let before = chunk.get_block(local.x, local.y, local.z);
+        if before == block {
+            return Some(before);
+        }
+        chunk.set_block(local.x, local.y, local.z, block);
+        self.modified.insert(coords);
+        self.edited.insert(coords);
+        //neighbours culled their faces against the old block
+        for offset in border_neighbours(local, size) {
+            if self.chunks.contains_key(&(coords + offset)) {
+                self.edited.insert(coords + offset);
+            }
+        }
+        Some(before)
+    }
+
+    //queues generation of chunks that came into range of `center` (closest first),
+    //unloads the ones that left it and picks up whatever the workers finished
+    pub fn update(&mut self, center: cgmath::Point3<f32>) {
+        self.receive_jobs();
+        self.remesh_edited();
+
+        let (center, _) = WorldPos::from_point(center).to_chunk(self.config.chunk_size);
+
+        let distance = self.config.render_distance;
+        let vertical_distance = self.config.vertical_render_distance;
+        //one chunk of slack so walking along a border doesn't reload chunks every frame
+        let unload_distance = (distance + 1) * (distance + 1);
+        let unloaded: Vec<ChunkPos> = self
+            .chunks
+            .keys()
+            .chain(self.generating.keys())
+            .filter(|coords| {
+                distance_squared(**coords, center) > unload_distance
+                    || (coords.y - center.y).abs() > vertical_distance + 1
+            })
+            .copied()
+            .collect();
+        for coords in &unloaded {
+            if self.modified.remove(coords) {
+                self.save_chunk(*coords);
+            }
+            self.chunks.remove(coords);
+            self.needs_mesh.remove(coords);
+            for pending in [self.generating.remove(coords), self.meshing.remove(coords)]
+                .into_iter()
+                .flatten()
+            {
+                pending.token.cancel();
+            }
+        }
+        //the edge of the loaded area was culled against the chunks that are gone now
+        for coords in &unloaded {
+            self.mark_neighbours(*coords);
+        }
+        if !unloaded.is_empty() {
+            self.flush_storage(center);
+        }
+
+        let mut missing = Vec::new();
+        for z in -distance..=distance {
+            for y in -vertical_distance..=vertical_distance {
+                for x in -distance..=distance {
+                    let coords = center + Vector3::new(x, y, z);
+                    if distance_squared(coords, center) <= distance * distance
+                        && !self.chunks.contains_key(&coords)
+                        && !self.generating.contains_key(&coords)
+                    {
+                        missing.push(coords);
+                    }
+                }
+            }
+        }
+        missing.sort_by_key(|coords| load_priority(*coords, center));
+        for coords in missing.into_iter().take(self.config.chunks_per_frame) {
+            self.queue_generation(coords);
+        }
+
+        let mut to_mesh: Vec<ChunkPos> = self
+            .needs_mesh
+            .iter()
+            .filter(|coords| !self.meshing.contains_key(coords))
+            .copied()
+            .collect();
+        to_mesh.sort_by_key(|coords| load_priority(*coords, center));
+        for coords in to_mesh.into_iter().take(self.config.chunks_per_frame) {
+            self.needs_mesh.remove(&coords);
+            self.queue_mesh(coords);
+        }
+    }
+
+    //writes every modified chunk that is still loaded to disk
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        for coords in self.modified.drain() {
+            if let Some(chunk) = self.chunks.get(&coords) {
+                storage.save_chunk(chunk)?;
+            }
+        }
+        storage.flush()
+    }
+
+    //failing to save shouldn't take the game down, the chunk just
+    //gets generated again next time
+    fn save_chunk(&self, coords: ChunkPos) {
+        let (Some(storage), Some(chunk)) = (&self.storage, self.chunks.get(&coords)) else {
+            return;
+        };
+        if let Err(e) = storage.save_chunk(chunk) {
+            log::error!("couldn't save chunk {:?}: {}", coords, e);
+        }
+    }
+
+    //syncing the files to disk can take long enough to drop frames, so
+    //it happens on a worker. regions the loaded area can't reach anymore
+    //are dropped from memory afterwards
+    fn flush_storage(&mut self, center: ChunkPos) {
+        let Some(storage) = self.storage.clone() else {
+            return;
+        };
+        let distance = self.config.render_distance + 1;
+        let vertical_distance = self.config.vertical_render_distance + 1;
+        self.jobs.spawn(CancelToken::default(), move || {
+            if let Err(e) = storage.flush() {
+                log::error!("couldn't save the world: {}", e);
+            }
+            let reaches = |start: i32, center: i32| {
+                start <= center + distance && center - distance < start + REGION_SIZE
+            };
+            storage.evict(|region| {
+                reaches(region.x * REGION_SIZE, center.x)
+                    && reaches(region.z * REGION_SIZE, center.z)
+                    && (region.y - center.y).abs() <= vertical_distance
+            });
+            JobOutput::Flushed
+        });
+    }
+
+    fn mesh_options(&self) -> MeshOptions {
+        MeshOptions {
+            mode: self.config.meshing_mode,
+            color_seed: self.config.color_variation.then_some(self.config.seed),
+        }
+    }
+
+    fn remesh_edited(&mut self) {
+        let options = self.mesh_options();
+        for coords in std::mem::take(&mut self.edited) {
+            //a worker could still be meshing the old blocks
+            if let Some(pending) = self.meshing.remove(&coords) {
+                pending.token.cancel();
+            }
+            self.needs_mesh.remove(&coords);
+            generate_mesh(&mut self.chunks, &self.registry, options, coords);
+        }
+    }
+
+    fn next_job(&mut self) -> PendingJob {
+        self.next_job_id += 1;
+        PendingJob {
+            id: self.next_job_id,
+            token: CancelToken::default(),
+        }
+    }
+
+    fn queue_generation(&mut self, coords: ChunkPos) {
+        let pending = self.next_job();
+        let job_id = pending.id;
+        let generator = self.generator.clone();
+        let storage = self.storage.clone();
+        let size = self.config.chunk_size;
+        self.jobs.spawn(pending.token.clone(), move || {
+            let saved = storage.and_then(|storage| {
+                storage.load_chunk(coords).unwrap_or_else(|e| {
+                    log::error!("regenerating chunk {:?}: {}", coords, e);
+                    None
+                })
+            });
+            let chunk = saved.unwrap_or_else(|| {
+                let mut chunk = ChunkMeshData::new(coords, size);
+                generator.generate(&mut chunk);
+                //carving and structures can leave unused palette entries behind
+                chunk.chunk_data.compact();
+                chunk
+            });
+            JobOutput::Generated {
+                coords,
+                job_id,
+                chunk: Box::new(chunk),
+            }
+        });
+        self.generating.insert(coords, pending);
+    }
+
+    //the worker gets copies of the chunk and its loaded neighbours so
+    //faces between chunks are culled the same way as on the main thread
+    fn queue_mesh(&mut self, coords: ChunkPos) {
+        let Some(chunk) = self.chunks.get(&coords) else {
+            return;
+        };
+        let mut snapshot = HashMap::new();
+        snapshot.insert(coords, chunk.snapshot());
+        for offset in NEIGHBOURS {
+            if let Some(neighbour) = self.chunks.get(&(coords + offset)) {
+                snapshot.insert(coords + offset, neighbour.snapshot());
+            }
+        }
+
+        let pending = self.next_job();
+        let job_id = pending.id;
+        let registry = self.registry.clone();
+        let options = self.mesh_options();
+        self.jobs.spawn(pending.token.clone(), move || {
+            generate_mesh(&mut snapshot, &registry, options, coords);
+            let (vertices, indices) = snapshot.get_mut(&coords).unwrap().take_mesh();
+            JobOutput::Meshed {
+                coords,
+                job_id,
+                vertices,
+                indices,
+            }
+        });
+        self.meshing.insert(coords, pending);
+    }
+
+    fn receive_jobs(&mut self) {
+        for output in self.jobs.poll() {
+            match output {
+                JobOutput::Generated {
+                    coords,
+                    job_id,
+                    chunk,
+                } => {
+                    if !is_pending(&mut self.generating, coords, job_id) {
+                        continue;
+                    }
+                    self.chunks.insert(coords, *chunk);
+                    //the neighbours were meshed with air on this side
+                    self.needs_mesh.insert(coords);
+                    self.mark_neighbours(coords);
+                }
+                JobOutput::Meshed {
+                    coords,
+                    job_id,
+                    vertices,
+                    indices,
+                } => {
+                    if !is_pending(&mut self.meshing, coords, job_id) {
+                        continue;
+                    }
+                    if let Some(chunk) = self.chunks.get_mut(&coords) {
+                        chunk.set_mesh(vertices, indices);
+                    }
+                }
+                JobOutput::Flushed => {}
+            }
+        }
+    }
+
+    fn mark_neighbours(&mut self, coords: ChunkPos) {
+        for offset in NEIGHBOURS {
+            if self.chunks.contains_key(&(coords + offset)) {
+                self.needs_mesh.insert(coords + offset);
+            }
+        }
+    }
+}
+
+fn get_block(chunks: &HashMap<ChunkPos, ChunkMeshData>, size: ChunkSize, pos: WorldPos) -> BlockId {
+    let (chunk, local) = pos.to_chunk(size);
+    match chunks.get(&chunk) {
+        Some(chunk) => chunk.get_block(local.x, local.y, local.z),
+        None => AIR,
+    }
+}
+
+//meshes a single chunk, faces against neighbouring chunks are culled
+//using their data, unloaded neighbours count as air
+fn generate_mesh(
+    chunks: &mut HashMap<ChunkPos, ChunkMeshData>,
+    registry: &BlockRegistry,
+    options: MeshOptions,
+    coords: ChunkPos,
+) -> u32 {
+    //taken out of the map so the neighbours can be borrowed while meshing
+    let Some(mut chunk) = chunks.remove(&coords) else {
+        return 0;
+    };
+    let (origin, size) = (chunk.origin(), chunk.size());
+    let faces = chunk.generate_mesh(registry, options, |x, y, z| {
+        get_block(chunks, size, origin + Vector3::new(x, y, z))
+    });
+    chunks.insert(coords, chunk);
+    faces
+}
+
+//offsets of the chunks next to a block on the border of its chunk
+fn border_neighbours(local: LocalPos, size: ChunkSize) -> Vec<Vector3<i32>> {
+    let mut offsets = Vec::new();
+    let axes = [
+        (local.x, size.width, Vector3::unit_x()),
+        (local.y, size.height, Vector3::unit_y()),
+        (local.z, size.depth, Vector3::unit_z()),
+    ];
+    for (position, length, axis) in axes {
+        if position == 0 {
+            offsets.push(-axis);
+        }
+        if position + 1 == length {
+            offsets.push(axis);
+        }
+    }
+    offsets
+}
+
+//removes the pending entry if the finished job is the one we're waiting for
+fn is_pending(pending: &mut HashMap<ChunkPos, PendingJob>, coords: ChunkPos, job_id: u64) -> bool {
+    match pending.get(&coords) {
+        Some(job) if job.id == job_id => {
+            pending.remove(&coords);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::palette::PaletteStorage;
+
+    const SIZE: ChunkSize = ChunkSize::DEFAULT;
+
+    fn solid(coords: ChunkPos, block: BlockId) -> ChunkMeshData {
+        let mut chunk = ChunkMeshData::new(coords, SIZE);
+        chunk.chunk_data = PaletteStorage::new(SIZE.volume(), block);
+        chunk
+    }
+
+    struct Empty;
+
+    impl TerrainGenerator for Empty {
+        fn generate(&self, _: &mut ChunkMeshData) {}
+    }
+
+    //a world with a few chunks loaded by hand, nothing gets generated
+    pub(crate) fn world(loaded: &[ChunkPos]) -> World {
+        let config = WorldConfig {
+            render_distance: 0,
+            vertical_render_distance: 0,
+            chunks_per_frame: 0,
+            meshing_mode: MeshingMode::Greedy,
+            seed: 0,
+            chunk_size: SIZE,
+            color_variation: false,
+            save_dir: None,
+        };
+        let mut world = World::new(BlockRegistry::builtin(), config, Arc::new(Empty));
+        for &coords in loaded {
+            world.chunks.insert(coords, solid(coords, AIR));
+        }
+        world
+    }
+
+    #[test]
+    fn edits_remesh_the_chunk_and_its_neighbours() {
+        let (chunk, right, above) = (
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(1, 0, 0),
+            ChunkPos::new(0, 1, 0),
+        );
+        let mut world = world(&[chunk, right, above]);
+        let stone = world.registry.id("stone").unwrap();
+
+        assert!(world.set_block(WorldPos::new(5, 5, 5), stone));
+        assert_eq!(world.edited, HashSet::from([chunk]));
+        world.remesh_edited();
+        assert!(world.edited.is_empty());
+        //a single block, 6 faces of 4 vertices
+        let (vertices, _) = world.chunks.get_mut(&chunk).unwrap().take_mesh();
+        assert_eq!(vertices.len(), 24);
+
+        //on the corner next to both loaded neighbours
+        assert!(world.set_block(WorldPos::new(15, 15, 0), stone));
+        assert_eq!(world.edited, HashSet::from([chunk, right, above]));
+        assert!(world.modified.contains(&chunk));
+        assert!(!world.set_block(WorldPos::new(-1, 0, 0), stone));
+    }
+
+    #[test]
+    fn edits_can_be_undone() {
+        let mut world = world(&[ChunkPos::new(0, 0, 0)]);
+        let (stone, dirt) = (
+            world.registry.id("stone").unwrap(),
+            world.registry.id("dirt").unwrap(),
+        );
+        let pos = WorldPos::new(1, 2, 3);
+        world.set_block(pos, stone);
+        world.set_block(pos, dirt);
+
+        assert!(world.undo());
+        assert_eq!(world.get_block(pos), stone);
+        assert!(world.undo());
+        assert_eq!(world.get_block(pos), AIR);
+        assert!(!world.undo());
+
+        assert!(world.redo());
+        assert_eq!(world.get_block(pos), stone);
+        assert!(world.redo());
+        assert_eq!(world.get_block(pos), dirt);
+        assert!(!world.redo());
+    }
+
+    #[test]
+    fn stacked_chunks_cull_faces_between_them() {
+        let registry = BlockRegistry::builtin();
+        let stone = registry.id("stone").unwrap();
+        let (below, above) = (ChunkPos::new(2, -1, 0), ChunkPos::new(2, 0, 0));
+        let mut chunks = HashMap::new();
+        chunks.insert(below, solid(below, stone));
+        chunks.insert(above, solid(above, stone));
+        let options = MeshOptions {
+            mode: MeshingMode::Greedy,
+            color_seed: None,
+        };
+
+        //every side but the shared one
+        assert_eq!(generate_mesh(&mut chunks, &registry, options, below), 5);
+        assert_eq!(generate_mesh(&mut chunks, &registry, options, above), 5);
+
+        chunks.remove(&above);
+        assert_eq!(generate_mesh(&mut chunks, &registry, options, below), 6);
+    }
+
+    #[test]
+    fn side_by_side_chunks_cull_faces_between_them() {
+        let registry = BlockRegistry::builtin();
+        let stone = registry.id("stone").unwrap();
+        let center = ChunkPos::new(0, 0, 0);
+        let sides = [ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 0, 1)];
+        let mut chunks = HashMap::new();
+        for coords in sides.into_iter().chain([center]) {
+            chunks.insert(coords, solid(coords, stone));
+        }
+        let options = MeshOptions {
+            mode: MeshingMode::Greedy,
+            color_seed: None,
+        };
+
+        //a quad for every side except the ones against x - 1 and z + 1
+        assert_eq!(generate_mesh(&mut chunks, &registry, options, center), 4);
+        for coords in sides {
+            assert_eq!(generate_mesh(&mut chunks, &registry, options, coords), 5);
+        }
+    }
+
+    //everything the world is loading or has loaded
+    fn requested(world: &World) -> HashSet<ChunkPos> {
+        world
+            .chunks
+            .keys()
+            .chain(world.generating.keys())
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn closest_chunks_are_loaded_first() {
+        let mut world = world(&[]);
+        world.config.render_distance = 2;
+        world.config.chunks_per_frame = 3;
+        let center = ChunkPos::new(0, 0, 0);
+
+        world.update(cgmath::Point3::new(8.0, 8.0, 8.0));
+        let first = requested(&world);
+        assert_eq!(first.len(), 3);
+        assert!(first.contains(&center));
+        assert!(first.iter().all(|&c| load_priority(c, center) <= 1));
+
+        //the other chunk next to the center comes before the diagonals
+        world.update(cgmath::Point3::new(8.0, 8.0, 8.0));
+        let second = requested(&world);
+        assert_eq!(second.len(), 6);
+        let sides = second.iter().filter(|&&c| load_priority(c, center) == 1);
+        assert_eq!(sides.count(), 4);
+    }
+
+    #[test]
+    fn loads_columns_within_the_vertical_range() {
+        let mut world = world(&[]);
+        world.config.render_distance = 1;
+        world.config.vertical_render_distance = 1;
+        world.config.chunks_per_frame = 100;
+
+        //in chunk (0, 2, 0)
+        world.update(cgmath::Point3::new(8.0, 40.0, 8.0));
+        let requested = requested(&world);
+        //a plus shape of 5 columns, 3 chunks high
+        assert_eq!(requested.len(), 15);
+        assert!(requested.iter().all(|c| (1..=3).contains(&c.y)));
+        let center = ChunkPos::new(0, 2, 0);
+        assert!(requested.iter().all(|&c| distance_squared(c, center) <= 1));
+    }
+
+    #[test]
+    fn chunks_out_of_range_get_unloaded() {
+        let kept = [
+            ChunkPos::new(0, 0, 0),
+            //one chunk of slack past the render distance
+            ChunkPos::new(2, 0, 0),
+            ChunkPos::new(0, 0, -2),
+            ChunkPos::new(0, 1, 0),
+        ];
+        let dropped = [
+            ChunkPos::new(3, 0, 0),
+            ChunkPos::new(2, 0, 2),
+            ChunkPos::new(0, 2, 0),
+            ChunkPos::new(0, -2, 0),
+        ];
+        let loaded: Vec<ChunkPos> = kept.iter().chain(&dropped).copied().collect();
+        let mut world = world(&loaded);
+        world.config.render_distance = 1;
+
+        world.update(cgmath::Point3::new(8.0, 8.0, 8.0));
+        let loaded: HashSet<ChunkPos> = world.chunks.keys().copied().collect();
+        assert_eq!(loaded, HashSet::from(kept));
+    }
+
+    #[test]
+    fn jobs_of_unloaded_chunks_get_cancelled() {
+        let near = ChunkPos::new(0, 0, 0);
+        let far = ChunkPos::new(5, 0, 0);
+        let mut world = world(&[near]);
+        let mut pending = Vec::new();
+        for coords in [near, far] {
+            for jobs in [&mut world.generating, &mut world.meshing] {
+                let token = CancelToken::default();
+                pending.push((coords, token.clone()));
+                jobs.insert(coords, PendingJob { id: 0, token });
+            }
+        }
+
+        world.update(cgmath::Point3::new(8.0, 8.0, 8.0));
+        for (coords, token) in pending {
+            assert_eq!(token.is_cancelled(), coords == far, "{:?}", coords);
+        }
+        assert!(!world.generating.contains_key(&far) && !world.meshing.contains_key(&far));
+        assert!(world.generating.contains_key(&near) && world.meshing.contains_key(&near));
+    }
+}